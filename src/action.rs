@@ -19,11 +19,21 @@
 * along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use strum::AsRefStr;
+use strum::{AsRefStr, IntoEnumIterator};
 use strum_macros::{Display, EnumIter, EnumString};
-use tao::event_loop::ControlFlow;
+use tao::event_loop::{ControlFlow, EventLoopWindowTarget};
 
-use crate::{key::KeyMode, state::State};
+use crate::{
+    cookie::CookieRule,
+    event::UserEvent,
+    hint::{HintAction, HintTarget},
+    key::KeyMode,
+    state::State,
+};
+
+/// The largest count an action is repeated by; longer counts are clamped
+/// to it.
+pub const MAX_COUNT: usize = 9999;
 
 #[derive(AsRefStr, Default, Clone, Debug, EnumIter, EnumString, Display)]
 #[strum(serialize_all = "kebab-case")]
@@ -53,23 +63,259 @@ pub enum Action {
     Search(String),
     SearchNext,
     SearchPrev,
+    ShowHistory(String),
+    HistoryClear,
+    Echo(String),
+    EchoError(String),
+    Set(String, String),
+    Bind(KeyMode, String, String),
+    Unbind(KeyMode, String),
+    ReloadBindings,
+    Command(String),
+    Complete(String),
+    UrlComplete(String),
+    /// A URL bar suggestion picked by its index, with the URL it showed.
+    UrlSelect(usize, String),
+    TabNew(String),
+    TabClose,
+    TabNext,
+    TabPrev,
+    TabMove(String),
+    TabMoveLeft,
+    TabMoveRight,
+    TabDuplicate,
+    TabUndo,
+    Buffer(usize),
+    ShowBuffers,
+    WindowNew(String),
+    WindowClose,
+    SessionSave(String),
+    SessionLoad(String),
+    MarkSet(String),
+    MarkJump(String),
+    BookmarkAdd(String),
+    BookmarkRemove(String),
+    ShowBookmarks(String),
+    BookmarksImport(String),
+    BookmarksExport(String),
+    QuickmarkSet(String),
+    QuickmarkOpen(String),
+    CookiesAllow(String),
+    CookiesBlock(String),
+    ShowCookies(String),
+    CookieDelete(String, String, String),
+    CookiesClear(String),
+    CookiesClearAll,
+    HintWindow(HintTarget),
+    HintYankUrl(HintTarget),
+    HintYankText(HintTarget),
+    HintDownload(HintTarget),
+    HintFocus(HintTarget),
+    HintHover(HintTarget),
+    HintRightClick(HintTarget),
+    HintExternal(HintTarget),
+    HintBackground(HintTarget),
+    HintSelectOpen(HintTarget),
+    HintSelectYank(HintTarget),
+    /// A hint the page could not act on by itself, with its URL or text.
+    Hinted(HintAction, String),
 }
 
 impl Action {
-    pub fn apply(&self, state: &mut State, control_flow: &mut ControlFlow) {
+    /// Whether the action can be run by its name on the command line. The
+    /// messages between the page and peyvand, and actions with a command
+    /// of their own, cannot.
+    pub fn is_command(&self) -> bool {
+        matches!(
+            self,
+            Action::GoBack
+                | Action::GoForward
+                | Action::ScrollDown
+                | Action::ScrollUp
+                | Action::ScrollTop
+                | Action::ScrollBottom
+                | Action::ScrollHalfUp
+                | Action::ScrollHalfDown
+                | Action::ShowHelp
+                | Action::Exit
+                | Action::NormalMode
+                | Action::InsertMode
+                | Action::CmdMode
+                | Action::SearchMode
+                | Action::HintMode
+                | Action::ShowURL
+                | Action::ChangeURL(_)
+                | Action::HardRefreshURL
+                | Action::SoftRefreshURL
+                | Action::CopyURL
+                | Action::PasteURL
+                | Action::Search(_)
+                | Action::SearchNext
+                | Action::SearchPrev
+                | Action::ShowHistory(_)
+                | Action::HistoryClear
+                | Action::Echo(_)
+                | Action::ReloadBindings
+                | Action::TabNew(_)
+                | Action::TabClose
+                | Action::TabNext
+                | Action::TabPrev
+                | Action::TabMove(_)
+                | Action::TabMoveLeft
+                | Action::TabMoveRight
+                | Action::TabDuplicate
+                | Action::TabUndo
+                | Action::ShowBuffers
+                | Action::WindowNew(_)
+                | Action::WindowClose
+                | Action::SessionSave(_)
+                | Action::SessionLoad(_)
+                | Action::MarkSet(_)
+                | Action::MarkJump(_)
+                | Action::BookmarkAdd(_)
+                | Action::BookmarkRemove(_)
+                | Action::ShowBookmarks(_)
+                | Action::BookmarksImport(_)
+                | Action::BookmarksExport(_)
+                | Action::QuickmarkSet(_)
+                | Action::QuickmarkOpen(_)
+                | Action::CookiesAllow(_)
+                | Action::CookiesBlock(_)
+                | Action::ShowCookies(_)
+                | Action::CookieDelete(..)
+                | Action::CookiesClear(_)
+                | Action::CookiesClearAll
+                | Action::HintWindow(_)
+                | Action::HintYankUrl(_)
+                | Action::HintYankText(_)
+                | Action::HintDownload(_)
+                | Action::HintFocus(_)
+                | Action::HintHover(_)
+                | Action::HintRightClick(_)
+                | Action::HintExternal(_)
+                | Action::HintBackground(_)
+                | Action::HintSelectOpen(_)
+                | Action::HintSelectYank(_)
+        )
+    }
+
+    /// Fills the parameter of an action parsed by name from the remaining
+    /// command-line arguments.
+    pub fn with_args(self, args: &[String]) -> Result<Self, String> {
+        let joined = args.join(" ");
         match self {
-            Action::GoBack => state.go_back(),
-            Action::GoForward => state.go_forward(),
-            Action::ScrollDown => state.scroll_down(),
-            Action::ScrollUp => state.scroll_up(),
-            Action::ScrollHalfDown => state.scroll_half_down(),
-            Action::ScrollHalfUp => state.scroll_half_up(),
+            Action::ChangeURL(_) if !args.is_empty() => Ok(Action::ChangeURL(joined)),
+            Action::Search(_) if !args.is_empty() => Ok(Action::Search(joined)),
+            Action::Echo(_) => Ok(Action::Echo(joined)),
+            Action::Command(_) => Ok(Action::Command(joined)),
+            Action::Complete(_) => Ok(Action::Complete(joined)),
+            Action::UrlComplete(_) => Ok(Action::UrlComplete(joined)),
+            Action::EchoError(_) => Ok(Action::EchoError(joined)),
+            Action::TabNew(_) => Ok(Action::TabNew(joined)),
+            Action::ShowHistory(_) => Ok(Action::ShowHistory(joined)),
+            Action::WindowNew(_) => Ok(Action::WindowNew(joined)),
+            Action::TabMove(_) => Ok(Action::TabMove(joined)),
+            Action::SessionSave(_) => Ok(Action::SessionSave(joined)),
+            Action::SessionLoad(_) => Ok(Action::SessionLoad(joined)),
+            Action::MarkSet(_) => Ok(Action::MarkSet(joined)),
+            Action::MarkJump(_) => Ok(Action::MarkJump(joined)),
+            Action::BookmarkAdd(_) => Ok(Action::BookmarkAdd(joined)),
+            Action::BookmarkRemove(_) => Ok(Action::BookmarkRemove(joined)),
+            Action::ShowBookmarks(_) => Ok(Action::ShowBookmarks(joined)),
+            Action::QuickmarkSet(_) => Ok(Action::QuickmarkSet(joined)),
+            Action::QuickmarkOpen(_) => Ok(Action::QuickmarkOpen(joined)),
+            Action::CookiesAllow(_) => Ok(Action::CookiesAllow(joined)),
+            Action::CookiesBlock(_) => Ok(Action::CookiesBlock(joined)),
+            Action::ShowCookies(_) => Ok(Action::ShowCookies(joined)),
+            Action::CookiesClear(_) => Ok(Action::CookiesClear(joined)),
+            Action::HintWindow(_) => Ok(Action::HintWindow(hint_target(args, HintTarget::Links)?)),
+            Action::HintYankUrl(_) => {
+                Ok(Action::HintYankUrl(hint_target(args, HintTarget::Links)?))
+            }
+            Action::HintYankText(_) => {
+                Ok(Action::HintYankText(hint_target(args, HintTarget::All)?))
+            }
+            Action::HintDownload(_) => {
+                Ok(Action::HintDownload(hint_target(args, HintTarget::Links)?))
+            }
+            Action::HintFocus(_) => Ok(Action::HintFocus(hint_target(args, HintTarget::Inputs)?)),
+            Action::HintHover(_) => Ok(Action::HintHover(hint_target(args, HintTarget::All)?)),
+            Action::HintRightClick(_) => {
+                Ok(Action::HintRightClick(hint_target(args, HintTarget::All)?))
+            }
+            Action::HintExternal(_) => {
+                Ok(Action::HintExternal(hint_target(args, HintTarget::Links)?))
+            }
+            Action::HintBackground(_) => Ok(Action::HintBackground(hint_target(
+                args,
+                HintTarget::Links,
+            )?)),
+            Action::HintSelectOpen(_) => Ok(Action::HintSelectOpen(hint_target(
+                args,
+                HintTarget::Links,
+            )?)),
+            Action::HintSelectYank(_) => Ok(Action::HintSelectYank(hint_target(
+                args,
+                HintTarget::Links,
+            )?)),
+            Action::CookieDelete(..) => match args {
+                [domain, name] => Ok(Action::CookieDelete(
+                    domain.clone(),
+                    name.clone(),
+                    String::new(),
+                )),
+                [domain, name, path] => Ok(Action::CookieDelete(
+                    domain.clone(),
+                    name.clone(),
+                    path.clone(),
+                )),
+                _ => Err(format!(
+                    "{self} requires a domain, a name and optionally a path"
+                )),
+            },
+            Action::BookmarksImport(_) | Action::BookmarksExport(_) if args.len() != 1 => {
+                Err(format!("{self} requires a file"))
+            }
+            Action::BookmarksImport(_) => Ok(Action::BookmarksImport(joined)),
+            Action::BookmarksExport(_) => Ok(Action::BookmarksExport(joined)),
+            Action::Buffer(_) => match args {
+                [n] => n
+                    .parse()
+                    .map(Action::Buffer)
+                    .map_err(|_| format!("Invalid buffer number: {n}")),
+                _ => Err(format!("{self} requires a buffer number")),
+            },
+            Action::ChangeURL(_) | Action::Search(_) => Err(format!("{self} requires an argument")),
+            _ if args.is_empty() => Ok(self),
+            _ => Err(format!("{self} takes no arguments")),
+        }
+    }
+
+    /// Applies the action `count` times. Motions fold the count into a single
+    /// step; actions where repetition makes no sense run once.
+    pub fn apply(
+        &self,
+        state: &mut State,
+        count: usize,
+        target: &EventLoopWindowTarget<UserEvent>,
+        control_flow: &mut ControlFlow,
+    ) {
+        match self {
+            Action::GoBack => state.go_back(count),
+            Action::GoForward => state.go_forward(count),
+            Action::ScrollDown => state.scroll_down(count),
+            Action::ScrollUp => state.scroll_up(count),
+            Action::ScrollHalfDown => state.scroll_half_down(count),
+            Action::ScrollHalfUp => state.scroll_half_up(count),
             Action::ScrollTop => state.scroll_top(),
             Action::ScrollBottom => state.scroll_bottom(),
             Action::NormalMode => state.set_key_mode(KeyMode::Normal),
             Action::InsertMode => state.set_key_mode(KeyMode::Insert),
-            Action::CmdMode => state.set_key_mode(KeyMode::Cmd),
-            Action::HintMode => state.set_key_mode(KeyMode::Hint),
+            Action::CmdMode => {
+                state.set_key_mode(KeyMode::Cmd);
+                state.send_command_history();
+            }
+            Action::HintMode => state.hint(HintAction::Follow, HintTarget::All),
             Action::SearchMode => state.set_key_mode(KeyMode::Search),
             Action::ShowHelp => state.show_help(),
             Action::ShowURL => {
@@ -88,8 +334,77 @@ impl Action {
             Action::Search(needle) => {
                 state.search(needle);
             }
-            Action::SearchNext => state.search_next(),
-            Action::SearchPrev => state.search_prev(),
+            Action::SearchNext => state.search_next(count),
+            Action::SearchPrev => state.search_prev(count),
+            Action::ShowHistory(filter) => state.show_history(filter),
+            Action::HistoryClear => state.clear_history(),
+            Action::Echo(msg) => state.echo(msg),
+            Action::EchoError(msg) => state.echo_error(msg),
+            Action::Set(key, value) => state.set_option(key, value),
+            Action::Bind(mode, keys, action) => state.bind(*mode, keys, action),
+            Action::Unbind(mode, keys) => state.unbind(*mode, keys),
+            Action::ReloadBindings => state.reload_bindings(),
+            Action::Command(line) => {
+                if let Ok((action, count)) = state.run_command(line) {
+                    action.apply(state, count, target, control_flow);
+                }
+            }
+            Action::Complete(line) => state.complete(line),
+            Action::UrlComplete(input) => state.url_complete(input),
+            Action::UrlSelect(index, value) => {
+                let (action, count) = state.url_select(*index, value);
+                action.apply(state, count, target, control_flow);
+            }
+            Action::TabNew(url) => state.tab_new(url),
+            Action::TabClose => {
+                if !state.tab_close() && !state.window_close() {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            Action::TabNext => state.tab_next(count),
+            Action::TabPrev => state.tab_prev(count),
+            Action::TabMove(position) => state.tab_move(position),
+            Action::TabMoveLeft => state.tab_shift(-(count as isize)),
+            Action::TabMoveRight => state.tab_shift(count as isize),
+            Action::TabDuplicate => state.tab_duplicate(),
+            Action::TabUndo => state.tab_undo(),
+            Action::Buffer(number) => state.buffer(*number),
+            Action::ShowBuffers => state.show_buffers(),
+            Action::WindowNew(url) => state.window_new(target, url),
+            Action::WindowClose => {
+                if !state.window_close() {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            Action::SessionSave(name) => state.session_save(name),
+            Action::SessionLoad(name) => state.session_load(target, name),
+            Action::MarkSet(name) => state.mark_set(name),
+            Action::MarkJump(name) => state.mark_jump(name),
+            Action::BookmarkAdd(tags) => state.bookmark_add(tags),
+            Action::BookmarkRemove(url) => state.bookmark_remove(url),
+            Action::ShowBookmarks(filter) => state.show_bookmarks(filter),
+            Action::BookmarksImport(path) => state.bookmarks_import(path),
+            Action::BookmarksExport(path) => state.bookmarks_export(path),
+            Action::QuickmarkSet(name) => state.quickmark_set(name),
+            Action::QuickmarkOpen(name) => state.quickmark_open(name),
+            Action::CookiesAllow(pattern) => state.cookies_rule(pattern, CookieRule::Allow),
+            Action::CookiesBlock(pattern) => state.cookies_rule(pattern, CookieRule::Block),
+            Action::ShowCookies(filter) => state.show_cookies(filter),
+            Action::CookieDelete(domain, name, path) => state.cookie_delete(domain, name, path),
+            Action::CookiesClear(domain) => state.cookies_clear(domain),
+            Action::CookiesClearAll => state.cookies_clear_all(),
+            Action::HintWindow(t) => state.hint(HintAction::Window, *t),
+            Action::HintYankUrl(t) => state.hint(HintAction::YankUrl, *t),
+            Action::HintYankText(t) => state.hint(HintAction::YankText, *t),
+            Action::HintDownload(t) => state.hint(HintAction::Download, *t),
+            Action::HintFocus(t) => state.hint(HintAction::Focus, *t),
+            Action::HintHover(t) => state.hint(HintAction::Hover, *t),
+            Action::HintRightClick(t) => state.hint(HintAction::RightClick, *t),
+            Action::HintExternal(t) => state.hint(HintAction::External, *t),
+            Action::HintBackground(t) => state.hint(HintAction::Background, *t),
+            Action::HintSelectOpen(t) => state.hint(HintAction::SelectOpen, *t),
+            Action::HintSelectYank(t) => state.hint(HintAction::SelectYank, *t),
+            Action::Hinted(action, value) => state.hinted(target, *action, value),
 
             Action::Exit => {
                 state.exit();
@@ -99,6 +414,21 @@ impl Action {
     }
 }
 
+/// Parses the optional hint target of a hint action.
+fn hint_target(args: &[String], default: HintTarget) -> Result<HintTarget, String> {
+    match args {
+        [] => Ok(default),
+        [target] => target.parse().map_err(|_| {
+            let targets: Vec<_> = HintTarget::iter().map(|t| t.to_string()).collect();
+            format!(
+                "Unknown hint target: {target} (one of {})",
+                targets.join(", ")
+            )
+        }),
+        _ => Err("Hint actions take at most one target".to_string()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
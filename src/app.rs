@@ -20,60 +20,100 @@
 */
 
 use crate::action::Action;
-use crate::{args::Args, state::State};
+use crate::{
+    args::Args, config::Config, event::UserEvent, signal::setup_signal_handlers, state::State,
+};
 use spdlog::{debug, info};
-use std::sync::Mutex;
 use tao::{
     event::{Event, StartCause, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
+    event_loop::{ControlFlow, EventLoopBuilder, EventLoopWindowTarget},
 };
 
 pub struct Application {
     args: Args,
+    config: Config,
 }
 
 impl Application {
     pub fn start(&mut self) -> anyhow::Result<()> {
-        let event_loop = EventLoop::new();
-        let (state, act_rx, nav_rx) = State::new(&self.args, &event_loop, &self.args.url)?;
-        let state = Mutex::new(state);
-        event_loop.run(move |event, _, control_flow| {
-            *control_flow = ControlFlow::Wait;
+        let event_loop = EventLoopBuilder::<UserEvent>::with_user_event().build();
+        let mut state = State::new(&self.args, &self.config, &event_loop)?;
+        setup_signal_handlers(event_loop.create_proxy())?;
+        debug!("Finished: setup signals");
 
-            while let Ok(url) = nav_rx.try_recv() {
-                state.lock().unwrap().set_url(url);
+        event_loop.run(move |event, target, control_flow| {
+            if let Event::NewEvents(StartCause::ResumeTimeReached { .. }) = event {
+                state.autosave();
             }
-            while let Ok(act) = act_rx.try_recv() {
-                dispatch_act(&state, control_flow, act);
+            // Wake up in time for the next crash-recovery save.
+            *control_flow = match state.autosave_deadline() {
+                Some(deadline) => ControlFlow::WaitUntil(deadline),
+                None => ControlFlow::Wait,
+            };
+
+            match event {
+                Event::UserEvent(UserEvent::LoadStarted(id, url)) => state.load_started(id, url),
+                Event::UserEvent(UserEvent::LoadFinished(id, url)) => state.load_finished(id, url),
+                Event::UserEvent(UserEvent::UserNavigation(id)) => state.user_navigation(id),
+                Event::UserEvent(UserEvent::NewWindow(url)) => state.page_window_new(target, &url),
+                Event::UserEvent(UserEvent::SameDocument(id, change, url)) => {
+                    state.same_document(id, change, url)
+                }
+                Event::UserEvent(UserEvent::TitleChanged(id, title)) => {
+                    state.title_changed(id, title)
+                }
+                Event::UserEvent(UserEvent::Scrolled(id, x, y)) => state.scrolled(id, x, y),
+                Event::UserEvent(UserEvent::Hints(id, count)) => state.label_hints(id, count),
+                Event::UserEvent(UserEvent::Download(id, event)) => state.download_event(id, event),
+                Event::UserEvent(UserEvent::Action(act, count)) => {
+                    dispatch_act(&mut state, target, control_flow, act, count)
+                }
+                event => handle_event(&mut state, event, control_flow),
             }
-            handle_event(event, control_flow);
         });
     }
 }
 
-fn handle_event(event: Event<'_, ()>, control_flow: &mut ControlFlow) {
+fn handle_event(state: &mut State, event: Event<'_, UserEvent>, control_flow: &mut ControlFlow) {
     match event {
         Event::NewEvents(StartCause::Init) => info!("Webview started"),
         Event::WindowEvent {
+            window_id,
             event: WindowEvent::CloseRequested,
-            ..
-        } => {
+        } if !state.close_window(window_id) => {
             info!("Peyvand exiting");
             *control_flow = ControlFlow::Exit
         }
+        Event::WindowEvent {
+            window_id,
+            event: WindowEvent::Resized(_),
+        } => state.resize(window_id),
+        Event::WindowEvent {
+            window_id,
+            event: WindowEvent::Focused(true),
+        } => state.focus(window_id),
         _ => {}
     }
 }
 
-fn dispatch_act(state: &Mutex<State>, control_flow: &mut ControlFlow, act: Action) {
-    let mode = state.lock().unwrap().get_key_mode();
-    debug!("Action in {mode} mode: {:#?}", act);
-    act.apply(&mut state.lock().unwrap(), control_flow)
+fn dispatch_act(
+    state: &mut State,
+    target: &EventLoopWindowTarget<UserEvent>,
+    control_flow: &mut ControlFlow,
+    act: Action,
+    count: usize,
+) {
+    debug!(
+        "Action in {} mode (x{count}): {:#?}",
+        state.get_key_mode(),
+        act
+    );
+    act.apply(state, count, target, control_flow)
 }
 
 impl Application {
-    pub fn new(args: Args) -> Self {
-        Self { args }
+    pub fn new(args: Args, config: Config) -> Self {
+        Self { args, config }
     }
 }
 
@@ -19,14 +19,15 @@
 * along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
+use crate::cookie::CookiePolicy;
 use clap::Parser;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None, term_width = 80)]
 pub struct Args {
-    /// Cookie policies
-    #[arg(short = 'a', default_value = "all")]
-    pub cookie_policies: Option<String>,
+    /// Cookie policies (all, none, session)
+    #[arg(short = 'a')]
+    pub cookie_policies: Option<CookiePolicy>,
 
     /// Scrollbars
     #[arg(short = 'b')]
@@ -36,6 +37,10 @@ pub struct Args {
     #[arg(short = 'c')]
     pub cookiefile: Option<String>,
 
+    /// Config file path
+    #[arg(long)]
+    pub config: Option<String>,
+
     /// Style file path
     #[arg(short = 'C')]
     pub stylefile: Option<String>,
@@ -76,6 +81,11 @@ pub struct Args {
     #[arg(short = 't')]
     pub stricttls: bool,
 
+    /// Restore a saved session: the named one, or else the crash-recovery
+    /// session if peyvand did not exit cleanly, or the one saved on exit
+    #[arg(long, value_name = "NAME", num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    pub restore: Option<String>,
+
     /// User agent string
     #[arg(short = 'u')]
     pub user_agent: Option<String>,
@@ -84,9 +94,8 @@ pub struct Args {
     #[arg(help = "Increment verbosity level (repeat for more detail, e.g., -vvv)")]
     pub verbose: u8,
 
-    /// Positional URL
-    #[arg(default_value = "about:blank")]
-    pub url: String,
+    /// Positional URL, defaults to the configured homepage
+    pub url: Option<String>,
 }
 
 #[allow(unused)]
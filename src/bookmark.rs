@@ -0,0 +1,410 @@
+/* bookmark.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::{config, fuzzy, history::now};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub url: String,
+    pub title: String,
+    pub tags: Vec<String>,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Data {
+    bookmarks: Vec<Bookmark>,
+    quickmarks: BTreeMap<char, String>,
+}
+
+/// Bookmarks and quickmarks, persisted in `bookmarks.json`.
+pub struct BookmarkStore {
+    file: Option<PathBuf>,
+    data: Data,
+}
+
+impl BookmarkStore {
+    pub fn load(file: Option<PathBuf>) -> Self {
+        let data = config::load_json(file.as_deref(), "bookmarks");
+        Self { file, data }
+    }
+
+    /// Bookmarks `url`, or updates the title and adds the tags if it already
+    /// is. Returns whether the bookmark is new.
+    pub fn add(&mut self, url: &str, title: &str, tags: &[String]) -> bool {
+        let added = self.insert(Bookmark {
+            url: url.to_string(),
+            title: title.to_string(),
+            tags: tags.to_vec(),
+            created: now(),
+        });
+        self.save();
+        added
+    }
+
+    fn insert(&mut self, bookmark: Bookmark) -> bool {
+        let Some(existing) = self
+            .data
+            .bookmarks
+            .iter_mut()
+            .find(|b| b.url == bookmark.url)
+        else {
+            self.data.bookmarks.push(bookmark);
+            return true;
+        };
+        if !bookmark.title.is_empty() {
+            existing.title = bookmark.title;
+        }
+        for tag in bookmark.tags {
+            if !existing.tags.contains(&tag) {
+                existing.tags.push(tag);
+            }
+        }
+        false
+    }
+
+    /// Removes the bookmark of `url` and returns whether there was one.
+    pub fn remove(&mut self, url: &str) -> bool {
+        let len = self.data.bookmarks.len();
+        self.data.bookmarks.retain(|b| b.url != url);
+        let removed = self.data.bookmarks.len() != len;
+        if removed {
+            self.save();
+        }
+        removed
+    }
+
+    /// Bookmarks matching `filter`, best match first. Words starting with
+    /// `#` select tags, the rest is matched fuzzily against the URL and
+    /// title; without a filter every bookmark is returned, newest first.
+    pub fn query(&self, filter: &str) -> Vec<&Bookmark> {
+        let (tags, words): (Vec<&str>, Vec<&str>) = filter
+            .split_whitespace()
+            .partition(|word| word.starts_with('#') && word.len() > 1);
+        let pattern = words.join(" ");
+        let mut matches: Vec<_> = self
+            .data
+            .bookmarks
+            .iter()
+            .filter(|b| tags.iter().all(|tag| b.tags.iter().any(|t| *t == tag[1..])))
+            .filter_map(|b| {
+                if pattern.is_empty() {
+                    return Some((0, b));
+                }
+                let score = fuzzy::score(&pattern, &b.url).max(fuzzy::score(&pattern, &b.title))?;
+                Some((score, b))
+            })
+            .collect();
+        matches.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .cmp(a_score)
+                .then(b.created.cmp(&a.created))
+                .then(a.url.cmp(&b.url))
+        });
+        matches.into_iter().map(|(_, b)| b).collect()
+    }
+
+    /// Parses the name of a quickmark, which is a single letter or digit.
+    pub fn parse_quickmark(name: &str) -> Result<char, String> {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphanumeric() => Ok(c),
+            _ => Err(format!("Invalid quickmark: {name}")),
+        }
+    }
+
+    pub fn set_quickmark(&mut self, name: char, url: &str) {
+        self.data.quickmarks.insert(name, url.to_string());
+        self.save();
+    }
+
+    pub fn quickmark(&self, name: char) -> Option<&str> {
+        self.data.quickmarks.get(&name).map(String::as_str)
+    }
+
+    /// Adds the bookmarks of a Netscape bookmark file and returns how many
+    /// were new.
+    pub fn import(&mut self, html: &str) -> usize {
+        let added = parse_netscape(html)
+            .into_iter()
+            .filter(|bookmark| self.insert(bookmark.clone()))
+            .count();
+        self.save();
+        added
+    }
+
+    /// Writes every bookmark as a Netscape bookmark file.
+    pub fn export(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+             <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+             <TITLE>Bookmarks</TITLE>\n\
+             <H1>Bookmarks</H1>\n\
+             <DL><p>\n",
+        );
+        for b in &self.data.bookmarks {
+            html.push_str(&format!(
+                "    <DT><A HREF=\"{}\" ADD_DATE=\"{}\"",
+                escape(&b.url),
+                b.created
+            ));
+            if !b.tags.is_empty() {
+                html.push_str(&format!(" TAGS=\"{}\"", escape(&b.tags.join(","))));
+            }
+            html.push_str(&format!(">{}</A>\n", escape(&b.title)));
+        }
+        html.push_str("</DL><p>\n");
+        html
+    }
+
+    fn save(&self) {
+        config::save_json(self.file.as_deref(), &self.data, "bookmarks");
+    }
+}
+
+/// Reads the links of a Netscape bookmark file. The names of the folders a
+/// link is in become its tags, in addition to those of its `TAGS` attribute.
+fn parse_netscape(html: &str) -> Vec<Bookmark> {
+    let mut bookmarks = Vec::new();
+    // Folders around the current position; the top-level list has no name.
+    let mut folders: Vec<Option<String>> = Vec::new();
+    // A folder heading, which is opened by the list that follows it.
+    let mut heading = None;
+
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = rest.find('>').unwrap_or(rest.len());
+        let tag = &rest[..end];
+        rest = &rest[(end + 1).min(rest.len())..];
+        let text = || decode(rest[..rest.find('<').unwrap_or(rest.len())].trim());
+
+        let name = tag.split_whitespace().next().unwrap_or_default();
+        match name.to_ascii_uppercase().as_str() {
+            "DL" => folders.push(heading.take()),
+            "/DL" => {
+                folders.pop();
+            }
+            "H3" => heading = Some(text()),
+            "A" => {
+                let Some(url) = attribute(tag, "HREF") else {
+                    continue;
+                };
+                let mut tags: Vec<String> = folders.iter().flatten().cloned().collect();
+                for tag in attribute(tag, "TAGS").unwrap_or_default().split(',') {
+                    let tag = tag.trim();
+                    if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+                        tags.push(tag.to_string());
+                    }
+                }
+                bookmarks.push(Bookmark {
+                    url,
+                    title: text(),
+                    tags,
+                    created: attribute(tag, "ADD_DATE")
+                        .and_then(|date| date.parse().ok())
+                        .unwrap_or_else(now),
+                });
+            }
+            _ => {}
+        }
+    }
+    bookmarks
+}
+
+/// The decoded value of a double-quoted attribute of a tag.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let needle = format!("{}=\"", name.to_ascii_lowercase());
+    let start = lower
+        .match_indices(&needle)
+        .find(|(i, _)| lower[..*i].ends_with(char::is_whitespace))?
+        .0
+        + needle.len();
+    let len = tag[start..].find('"')?;
+    Some(decode(&tag[start..start + len]))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decodes the character references of HTML text; unknown ones are kept.
+fn decode(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = rest[1..].find(';').map(|end| &rest[1..end + 1]);
+        let c = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let number = reference.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+        match (c, reference) {
+            (Some(c), Some(reference)) => {
+                decoded.push(c);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn test_add_and_remove() {
+        let mut store = BookmarkStore::load(None);
+        assert!(store.add("https://a.org/", "A", &tags(&["rust"])));
+        assert!(!store.add("https://a.org/", "A page", &tags(&["rust", "docs"])));
+        assert_eq!(store.query("").len(), 1);
+        assert_eq!(store.query("")[0].title, "A page");
+        assert_eq!(store.query("")[0].tags, tags(&["rust", "docs"]));
+
+        assert!(store.remove("https://a.org/"));
+        assert!(!store.remove("https://a.org/"));
+        assert!(store.query("").is_empty());
+    }
+
+    #[test]
+    fn test_query() {
+        let mut store = BookmarkStore::load(None);
+        store.add("https://docs.rs/", "Docs.rs", &tags(&["rust", "docs"]));
+        store.add("https://crates.io/", "crates.io", &tags(&["rust"]));
+        store.add("https://news.ycombinator.com/", "Hacker News", &[]);
+
+        let urls =
+            |filter| -> Vec<&str> { store.query(filter).iter().map(|b| b.url.as_str()).collect() };
+        assert_eq!(urls("#rust").len(), 2);
+        assert_eq!(urls("#rust #docs"), vec!["https://docs.rs/"]);
+        assert_eq!(urls("hn"), vec!["https://news.ycombinator.com/"]);
+        assert_eq!(urls("#rust crates"), vec!["https://crates.io/"]);
+        assert!(urls("#none").is_empty());
+    }
+
+    #[test]
+    fn test_quickmarks_are_persisted() {
+        let path =
+            std::env::temp_dir().join(format!("peyvand-bookmarks-{}.json", std::process::id()));
+        let mut store = BookmarkStore::load(Some(path.clone()));
+        store.set_quickmark('a', "https://a.org/");
+        store.add("https://b.org/", "B", &[]);
+
+        let store = BookmarkStore::load(Some(path.clone()));
+        assert_eq!(store.quickmark('a'), Some("https://a.org/"));
+        assert_eq!(store.quickmark('b'), None);
+        assert_eq!(store.query("").len(), 1);
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(BookmarkStore::parse_quickmark("a"), Ok('a'));
+        assert_eq!(BookmarkStore::parse_quickmark("7"), Ok('7'));
+        assert!(BookmarkStore::parse_quickmark("ab").is_err());
+        assert!(BookmarkStore::parse_quickmark("'").is_err());
+    }
+
+    #[test]
+    fn test_import_netscape() {
+        let html = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file. -->
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks Menu</H1>
+<DL><p>
+    <DT><H3 ADD_DATE="1700000000">Rust &amp; Co</H3>
+    <DL><p>
+        <DT><A HREF="https://docs.rs/?a=1&amp;b=2" ADD_DATE="1700000001" TAGS="docs,rust">Docs &lt;rs&gt;</A>
+        <DT><H3>Nested</H3>
+        <DL><p>
+            <dt><a href="https://crates.io/" add_date="1700000002">Crates</a>
+        </DL><p>
+    </DL><p>
+    <DT><A HREF="https://example.com/">Caf&#233; &#x2014; &bogus; a & b</A>
+    <DT><A NAME="no-link">Not a bookmark</A>
+</DL>
+"#;
+        let mut store = BookmarkStore::load(None);
+        assert_eq!(store.import(html), 3);
+        let bookmarks = &store.data.bookmarks;
+        assert_eq!(
+            bookmarks[0],
+            Bookmark {
+                url: "https://docs.rs/?a=1&b=2".to_string(),
+                title: "Docs <rs>".to_string(),
+                tags: tags(&["Rust & Co", "docs", "rust"]),
+                created: 1700000001,
+            }
+        );
+        assert_eq!(bookmarks[1].tags, tags(&["Rust & Co", "Nested"]));
+        assert_eq!(bookmarks[1].created, 1700000002);
+        assert_eq!(bookmarks[2].title, "Café — &bogus; a & b");
+        assert!(bookmarks[2].tags.is_empty());
+
+        assert_eq!(store.import(html), 0);
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let mut store = BookmarkStore::load(None);
+        store.add(
+            "https://a.org/?q=\"x\"&y",
+            "A <b> & \"c\"",
+            &tags(&["one", "two"]),
+        );
+        store.add("https://b.org/", "", &[]);
+
+        let mut imported = BookmarkStore::load(None);
+        assert_eq!(imported.import(&store.export()), 2);
+        assert_eq!(imported.data.bookmarks, store.data.bookmarks);
+    }
+}
+
+/* bookmark.rs ends here */
@@ -0,0 +1,256 @@
+/* cmdline.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::{
+    action::Action,
+    command,
+    config::{self, Settings},
+    key::KeyMode,
+};
+use serde::Serialize;
+use spdlog::error;
+use std::{collections::HashSet, path::PathBuf};
+use strum::IntoEnumIterator;
+
+const CMDLINE_JS: &str = r#"
+const cmdline = {
+  history: [],
+  historyIndex: -1,
+  completion: null,
+};
+
+exposeEntryPoint("setCommandHistory", (history) => {
+  cmdline.history = history;
+  cmdline.historyIndex = history.length;
+});
+
+const recallCommand = (step) => {
+  const cl = cmdline;
+  if (!cl.history.length) return;
+  cl.historyIndex = Math.max(0, Math.min(cl.history.length, cl.historyIndex + step));
+  appState.commandBuffer = cl.history[cl.historyIndex] || "";
+  updateStatus(":" + appState.commandBuffer);
+};
+
+exposeEntryPoint("showCompletions", (completion) => {
+  hideCompletions();
+  if (!completion.candidates.length) return;
+
+  const menu = document.createElement("div");
+  Object.assign(menu.style, {
+    position: "fixed",
+    bottom: "24px",
+    left: "0px",
+    width: "100%",
+    maxHeight: "30%",
+    overflowY: "auto",
+    backgroundColor: "rgba(0,0,0,0.9)",
+    color: "white",
+    fontFamily: "monospace",
+    fontSize: "14px",
+    zIndex: "999999",
+  });
+  completion.candidates.forEach((candidate) => {
+    const row = document.createElement("div");
+    row.textContent = candidate;
+    row.style.paddingLeft = "4px";
+    menu.appendChild(row);
+  });
+  const host = mountOverlay(menu);
+
+  cmdline.completion = {
+    prefix: appState.commandBuffer.slice(0, completion.start),
+    candidates: completion.candidates,
+    index: -1,
+    menu,
+    host,
+  };
+  cycleCompletion(1);
+});
+
+const cycleCompletion = (step) => {
+  const c = cmdline.completion;
+  if (!c) return;
+  const n = c.candidates.length;
+  c.index = c.index < 0 && step < 0 ? n - 1 : (c.index + step + n) % n;
+  Array.from(c.menu.children).forEach((row, i) => {
+    row.style.backgroundColor = i === c.index ? '#444' : "transparent";
+  });
+  c.menu.children[c.index].scrollIntoView({ block: "nearest" });
+  appState.commandBuffer = c.prefix + c.candidates[c.index];
+  updateStatus(":" + appState.commandBuffer);
+  if (n === 1) hideCompletions();
+};
+
+const hideCompletions = () => {
+  const c = cmdline.completion;
+  if (c) c.host.remove();
+  cmdline.completion = null;
+};
+"#;
+
+const MAX_HISTORY: usize = 500;
+
+pub struct Cmdline {}
+
+impl Cmdline {
+    pub fn get_js() -> &'static str {
+        CMDLINE_JS
+    }
+}
+
+/// Candidates for the word that starts at `start` (counted in characters).
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Completion {
+    pub start: usize,
+    pub candidates: Vec<String>,
+}
+
+/// Completes the last word of `line`; for `:open`, `urls` is asked for the
+/// URLs matching the word.
+pub fn complete(line: &str, urls: impl FnOnce(&str) -> Vec<String>) -> Completion {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let starting_word = line.is_empty() || line.ends_with(char::is_whitespace);
+    let word = if starting_word {
+        ""
+    } else {
+        words.last().copied().unwrap_or_default()
+    };
+    let position = if starting_word {
+        words.len()
+    } else {
+        words.len() - 1
+    };
+    let start = line.chars().count() - word.chars().count();
+
+    let command = words.first().map(|name| {
+        let name = name.trim_end_matches('!');
+        command::resolve_name(name).unwrap_or(name)
+    });
+
+    let mut candidates: Vec<String> = match (position, command) {
+        (0, _) => command::COMMANDS
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .chain(
+                Action::iter()
+                    .filter(Action::is_command)
+                    .map(|action| action.to_string()),
+            )
+            .collect(),
+        (1, Some("set")) => Settings::KEYS.iter().map(|k| k.to_string()).collect(),
+        (1, Some("bind" | "unbind")) => KeyMode::iter()
+            .map(|m| m.to_string().to_lowercase())
+            .collect(),
+        (3, Some("bind")) => action_names().collect(),
+        (1, Some("open" | "change-url")) => {
+            let candidates = urls(word);
+            return Completion { start, candidates };
+        }
+        _ => Vec::new(),
+    };
+
+    let mut seen = HashSet::new();
+    candidates.retain(|c| c.starts_with(word) && seen.insert(c.clone()));
+    Completion { start, candidates }
+}
+
+fn action_names() -> impl Iterator<Item = String> {
+    Action::iter().map(|action| action.to_string())
+}
+
+/// Previously executed command lines, persisted across sessions.
+pub struct CommandHistory {
+    file: Option<PathBuf>,
+    entries: Vec<String>,
+}
+
+impl CommandHistory {
+    pub fn load(file: Option<PathBuf>) -> Self {
+        let entries = file
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|data| data.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        Self { file, entries }
+    }
+
+    pub fn push(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| entry != line);
+        self.entries.push(line.to_string());
+        if self.entries.len() > MAX_HISTORY {
+            self.entries.drain(..self.entries.len() - MAX_HISTORY);
+        }
+        if let Err(e) = self.save() {
+            error!("Failed to save command history: {}", e);
+        }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(path) = &self.file {
+            config::write_atomic(path, &(self.entries.join("\n") + "\n"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete_command_names() {
+        let completion = complete("scr", |_| Vec::new());
+        assert_eq!(completion.start, 0);
+        assert!(completion.candidates.contains(&"scroll-down".to_string()));
+        assert!(completion.candidates.iter().all(|c| c.starts_with("scr")));
+
+        let completion = complete("o", |_| Vec::new());
+        assert_eq!(completion.candidates[0], "open");
+    }
+
+    #[test]
+    fn test_complete_arguments() {
+        let completion = complete("set sc", |_| Vec::new());
+        assert_eq!(completion.start, 4);
+        assert_eq!(completion.candidates, vec!["scroll-step"]);
+
+        let completion = complete("bind ", |_| Vec::new());
+        assert_eq!(completion.start, 5);
+        assert!(completion.candidates.contains(&"normal".to_string()));
+
+        let completion = complete("o rust", |word| vec![format!("https://{word}-lang.org")]);
+        assert_eq!(completion.start, 2);
+        assert_eq!(completion.candidates, vec!["https://rust-lang.org"]);
+
+        assert!(complete("quit now", |_| Vec::new()).candidates.is_empty());
+    }
+}
+
+/* cmdline.rs ends here */
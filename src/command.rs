@@ -0,0 +1,342 @@
+/* command.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::{
+    action::{Action, MAX_COUNT},
+    key::KeyMode,
+};
+use std::str::FromStr;
+
+/// Ex commands with the length of their shortest accepted abbreviation,
+/// e.g. `("open", 1)` accepts `:o`, `:op` and `:open`.
+pub const COMMANDS: &[(&str, usize)] = &[
+    ("open", 1),
+    ("quit", 1),
+    ("help", 1),
+    ("set", 2),
+    ("bind", 2),
+    ("unbind", 3),
+    ("back", 2),
+    ("forward", 2),
+    ("reload", 2),
+    ("yank", 1),
+    ("history", 3),
+    ("bookmarks", 5),
+    ("cookies", 4),
+    ("echo", 2),
+    ("tabnext", 4),
+    ("tabnew", 6),
+    ("tabprevious", 4),
+    ("tabclose", 4),
+    ("tabmove", 4),
+    ("tabduplicate", 4),
+    ("undo", 1),
+    ("buffer", 1),
+    ("buffers", 7),
+    ("winopen", 4),
+    ("winclose", 4),
+];
+
+/// Splits a command line into arguments, honouring single quotes (literal),
+/// double quotes (with backslash escapes) and backslash-escaped spaces.
+pub fn split_args(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            '\'' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c),
+                        None => return Err("Unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => current.push(c),
+                            None => return Err("Unterminated double quote".to_string()),
+                        },
+                        Some(c) => current.push(c),
+                        None => return Err("Unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => {
+                in_arg = true;
+                if let Some(c) = chars.next() {
+                    current.push(c);
+                }
+            }
+            c => {
+                in_arg = true;
+                current.push(c);
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Ok(args)
+}
+
+/// Resolves a possibly abbreviated command name to its full name.
+pub fn resolve_name(name: &str) -> Option<&'static str> {
+    COMMANDS
+        .iter()
+        .find(|(full, min)| name.len() >= *min && full.starts_with(name))
+        .map(|(full, _)| *full)
+}
+
+/// Parses an ex command line (without the leading `:`) into an [`Action`]
+/// and a repeat count.
+pub fn parse(line: &str) -> Result<(Action, usize), String> {
+    let line = line.trim().trim_start_matches(':');
+    let (name, rest) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim_start()),
+        None => (line, ""),
+    };
+    if name.is_empty() {
+        return Err("No command given".to_string());
+    }
+    let (name, bang) = match name.strip_suffix('!') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    let args = split_args(rest)?;
+
+    let Some(command) = resolve_name(name) else {
+        let action = Action::from_str(name)
+            .ok()
+            .filter(Action::is_command)
+            .ok_or_else(|| format!("Not a command: {name}"))?;
+        return Ok((action.with_args(&args)?, 1));
+    };
+
+    let action = match command {
+        "open" => Action::ChangeURL(join_required(&args, "open <url>")?),
+        "quit" => Action::Exit,
+        "help" => Action::ShowHelp,
+        "echo" => Action::Echo(args.join(" ")),
+        "yank" => Action::CopyURL,
+        "history" => Action::ShowHistory(args.join(" ")),
+        "bookmarks" => Action::ShowBookmarks(args.join(" ")),
+        "cookies" => Action::ShowCookies(args.join(" ")),
+        "reload" if bang => Action::HardRefreshURL,
+        "reload" => Action::SoftRefreshURL,
+        "tabnew" => Action::TabNew(args.join(" ")),
+        "tabclose" => Action::TabClose,
+        "tabmove" => match args.as_slice() {
+            [] => Action::TabMove(String::new()),
+            [position] => Action::TabMove(position.clone()),
+            _ => return Err("Usage: tabmove [N|+N|-N]".to_string()),
+        },
+        "tabduplicate" => Action::TabDuplicate,
+        "undo" => Action::TabUndo,
+        "buffer" => Action::Buffer(0).with_args(&args)?,
+        "buffers" => Action::ShowBuffers,
+        "winopen" => Action::WindowNew(args.join(" ")),
+        "winclose" => Action::WindowClose,
+        "set" => match args.as_slice() {
+            [key, value] => Action::Set(key.clone(), value.clone()),
+            [assignment] if assignment.contains('=') => {
+                let (key, value) = assignment.split_once('=').unwrap();
+                Action::Set(key.to_string(), value.to_string())
+            }
+            _ => return Err("Usage: set <key> <value>".to_string()),
+        },
+        "bind" => match args.as_slice() {
+            [mode, keys, action] => Action::Bind(parse_mode(mode)?, keys.clone(), action.clone()),
+            _ => return Err("Usage: bind <mode> <keys> <action>".to_string()),
+        },
+        "unbind" => match args.as_slice() {
+            [mode, keys] => Action::Unbind(parse_mode(mode)?, keys.clone()),
+            _ => return Err("Usage: unbind <mode> <keys>".to_string()),
+        },
+        "back" | "forward" | "tabnext" | "tabprevious" => {
+            let count = match args.as_slice() {
+                [] => 1,
+                [n] => n
+                    .parse::<usize>()
+                    .map_err(|_| format!("Invalid count: {n}"))?
+                    .min(MAX_COUNT),
+                _ => return Err(format!("Usage: {command} [N]")),
+            };
+            let action = match command {
+                "back" => Action::GoBack,
+                "forward" => Action::GoForward,
+                "tabnext" => Action::TabNext,
+                _ => Action::TabPrev,
+            };
+            return Ok((action, count));
+        }
+        _ => unreachable!("command {command} has no handler"),
+    };
+    Ok((action, 1))
+}
+
+/// Quotes an argument so that [`split_args`] reads it back unchanged.
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '\'' | '"' | '\\'))
+    {
+        return arg.to_string();
+    }
+    let escaped = arg.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{escaped}\"")
+}
+
+fn join_required(args: &[String], usage: &str) -> Result<String, String> {
+    if args.is_empty() {
+        Err(format!("Usage: {usage}"))
+    } else {
+        Ok(args.join(" "))
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<KeyMode, String> {
+    KeyMode::from_str(mode).map_err(|_| format!("Unknown mode: {mode}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(split_args("a  b").unwrap(), vec!["a", "b"]);
+        assert_eq!(
+            split_args(r#"'a b' "c \"d\"" e\ f"#).unwrap(),
+            vec!["a b", "c \"d\"", "e f"]
+        );
+        assert_eq!(split_args("''").unwrap(), vec![""]);
+        assert!(split_args("'a").is_err());
+        assert!(split_args("\"a").is_err());
+    }
+
+    #[test]
+    fn test_quote() {
+        for arg in ["plain", "", "a b", r#"it's "quoted" \ here"#, "tab\there"] {
+            let line = format!("open {}", quote(arg));
+            assert_eq!(split_args(&line).unwrap(), vec!["open", arg]);
+        }
+        assert_eq!(quote("https://a.org/?q=1"), "https://a.org/?q=1");
+    }
+
+    #[test]
+    fn test_abbreviations() {
+        assert_eq!(resolve_name("o"), Some("open"));
+        assert_eq!(resolve_name("open"), Some("open"));
+        assert_eq!(resolve_name("q"), Some("quit"));
+        assert_eq!(resolve_name("s"), None);
+        assert_eq!(resolve_name("opened"), None);
+        assert_eq!(resolve_name("his"), Some("history"));
+        assert_eq!(resolve_name("hi"), None);
+        assert_eq!(resolve_name("b"), Some("buffer"));
+        assert_eq!(resolve_name("buffers"), Some("buffers"));
+        assert_eq!(resolve_name("tabn"), Some("tabnext"));
+        assert_eq!(resolve_name("tabnew"), Some("tabnew"));
+        assert_eq!(resolve_name("u"), Some("undo"));
+        assert_eq!(resolve_name("unb"), Some("unbind"));
+        assert_eq!(resolve_name("wino"), Some("winopen"));
+        assert_eq!(resolve_name("bookm"), Some("bookmarks"));
+        assert_eq!(resolve_name("bookmark-add"), None);
+        assert_eq!(resolve_name("cook"), Some("cookies"));
+        assert_eq!(resolve_name("cookies-clear"), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(matches!(
+            parse("o https://example.com"),
+            Ok((Action::ChangeURL(url), 1)) if url == "https://example.com"
+        ));
+        assert!(matches!(parse("q"), Ok((Action::Exit, 1))));
+        assert!(matches!(parse("reload!"), Ok((Action::HardRefreshURL, 1))));
+        assert!(matches!(parse("back 3"), Ok((Action::GoBack, 3))));
+        assert!(matches!(
+            parse("set scroll-step 80"),
+            Ok((Action::Set(k, v), 1)) if k == "scroll-step" && v == "80"
+        ));
+        assert!(matches!(
+            parse("bind normal 'gg' scroll-top"),
+            Ok((Action::Bind(KeyMode::Normal, k, a), 1)) if k == "gg" && a == "scroll-top"
+        ));
+        assert!(matches!(
+            parse("change-url https://example.com"),
+            Ok((Action::ChangeURL(url), 1)) if url == "https://example.com"
+        ));
+        assert!(matches!(parse("scroll-down"), Ok((Action::ScrollDown, 1))));
+        assert!(parse("scroll-down 3").is_err());
+        assert!(parse("open").is_err());
+        assert!(parse("frobnicate").is_err());
+        assert!(parse("url-select").is_err());
+        assert!(parse("hinted").is_err());
+        assert!(parse("command quit").is_err());
+        assert!(parse("echo-error x").is_err());
+        assert!(parse("back x").is_err());
+        assert!(matches!(parse("b 2"), Ok((Action::Buffer(2), 1))));
+        assert!(parse("buffer").is_err());
+        assert!(parse("buffer two").is_err());
+        assert!(matches!(parse("tabn 2"), Ok((Action::TabNext, 2))));
+        assert!(matches!(
+            parse("tabmove +1"),
+            Ok((Action::TabMove(position), 1)) if position == "+1"
+        ));
+        assert!(matches!(
+            parse("bookmark-add rust docs"),
+            Ok((Action::BookmarkAdd(tags), 1)) if tags == "rust docs"
+        ));
+        assert!(matches!(
+            parse("bookmarks-import '~/My Bookmarks.html'"),
+            Ok((Action::BookmarksImport(path), 1)) if path == "~/My Bookmarks.html"
+        ));
+        assert!(parse("bookmarks-export").is_err());
+        assert!(matches!(
+            parse("cookie-delete example.com sid"),
+            Ok((Action::CookieDelete(d, n, p), 1)) if d == "example.com" && n == "sid" && p.is_empty()
+        ));
+        assert!(parse("cookie-delete example.com").is_err());
+        assert!(matches!(
+            parse("cookies-clear-all"),
+            Ok((Action::CookiesClearAll, 1))
+        ));
+    }
+}
+
+/* command.rs ends here */
@@ -0,0 +1,367 @@
+/* config.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::{
+    args::Args,
+    cookie::{CookiePolicy, CookieRule, CookieRules},
+    hint::{self, DEFAULT_HINT_ALPHABET},
+    key::KeybindingConfig,
+    url,
+};
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use spdlog::{debug, error, info};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+const CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Settings {
+    pub homepage: String,
+    pub scroll_step: i32,
+    pub user_agent: Option<String>,
+    pub cookie_policy: CookiePolicy,
+    /// Cookie rules by domain pattern, overriding the cookie policy.
+    pub cookie_rules: BTreeMap<String, CookieRule>,
+    /// Whether to drop cookies of sites not opened in a tab. Any site opened
+    /// during the session counts as first party in every tab from then on,
+    /// and the cookies are only dropped once a page has finished loading,
+    /// so requests made while it loads still carry them.
+    pub block_third_party_cookies: bool,
+    /// Days after which unvisited pages are dropped from the history, or 0
+    /// to keep them forever.
+    pub history_days: u64,
+    /// Seconds between crash-recovery saves of the session, or 0 to disable
+    /// them.
+    pub session_autosave: u64,
+    /// The search engine for URL bar input that is not a URL.
+    pub search_engine: String,
+    /// Search engines by keyword, in addition to the built-in ones, as URLs
+    /// with `{}` in place of the query.
+    pub search_engines: BTreeMap<String, String>,
+    /// The keys hint labels are made of.
+    pub hint_alphabet: String,
+    /// Whether all hint labels have the same length.
+    pub hint_uniform_labels: bool,
+    /// The program `hint-external` opens links with, `{}` standing for the
+    /// URL.
+    pub external_command: String,
+}
+
+impl Settings {
+    pub const KEYS: &'static [&'static str] = &[
+        "homepage",
+        "scroll-step",
+        "user-agent",
+        "cookie-policy",
+        "block-third-party-cookies",
+        "history-days",
+        "session-autosave",
+        "search-engine",
+        "hint-alphabet",
+        "hint-uniform-labels",
+        "external-command",
+    ];
+
+    /// Updates a setting from its `:set` representation. The user agent only
+    /// applies to webviews created afterwards.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "homepage" => self.homepage = value.to_string(),
+            "scroll-step" => {
+                self.scroll_step = value
+                    .parse()
+                    .map_err(|_| format!("Invalid scroll-step: {value}"))?
+            }
+            "user-agent" => {
+                self.user_agent = (!value.is_empty()).then(|| value.to_string());
+            }
+            "cookie-policy" => {
+                self.cookie_policy = value
+                    .parse()
+                    .map_err(|_| format!("Invalid cookie-policy: {value}"))?
+            }
+            "block-third-party-cookies" => {
+                self.block_third_party_cookies = value
+                    .parse()
+                    .map_err(|_| format!("Invalid block-third-party-cookies: {value}"))?
+            }
+            "history-days" => {
+                self.history_days = value
+                    .parse()
+                    .map_err(|_| format!("Invalid history-days: {value}"))?
+            }
+            "session-autosave" => {
+                self.session_autosave = value
+                    .parse()
+                    .map_err(|_| format!("Invalid session-autosave: {value}"))?
+            }
+            "search-engine" => {
+                if url::search_engine(value, &self.search_engines).is_none() {
+                    return Err(format!("Unknown search-engine: {value}"));
+                }
+                self.search_engine = value.to_string();
+            }
+            "hint-alphabet" => {
+                hint::parse_alphabet(value)?;
+                self.hint_alphabet = value.to_string();
+            }
+            "hint-uniform-labels" => {
+                self.hint_uniform_labels = value
+                    .parse()
+                    .map_err(|_| format!("Invalid hint-uniform-labels: {value}"))?
+            }
+            "external-command" => self.external_command = value.to_string(),
+            _ => {
+                return Err(format!(
+                    "Unknown option: {key} (one of {})",
+                    Self::KEYS.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            homepage: "about:blank".to_string(),
+            scroll_step: 40,
+            user_agent: None,
+            cookie_policy: CookiePolicy::All,
+            cookie_rules: BTreeMap::new(),
+            block_third_party_cookies: false,
+            history_days: 90,
+            session_autosave: 60,
+            search_engine: "ddg".to_string(),
+            search_engines: BTreeMap::new(),
+            hint_alphabet: DEFAULT_HINT_ALPHABET.to_string(),
+            hint_uniform_labels: false,
+            external_command: "xdg-open {}".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub settings: Settings,
+    #[serde(flatten)]
+    pub keybindings: KeybindingConfig,
+}
+
+/// Expands a leading `~` in a path given by the user.
+pub fn expand_path(path: &str) -> PathBuf {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Returns `$XDG_DATA_HOME/peyvand`, creating it if needed.
+pub fn data_dir() -> Option<PathBuf> {
+    let dir = dirs::data_dir()?.join("peyvand");
+    match std::fs::create_dir_all(&dir) {
+        Ok(()) => Some(dir),
+        Err(e) => {
+            error!("Failed to create data directory {}: {}", dir.display(), e);
+            None
+        }
+    }
+}
+
+/// Reads the JSON data file at `file`, falling back to the default when
+/// there is none or it is corrupt; `what` names it in the log.
+pub fn load_json<T: DeserializeOwned + Default>(file: Option<&Path>, what: &str) -> T {
+    file.and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|data| {
+            serde_json::from_str(&data)
+                .inspect_err(|e| error!("Ignoring corrupt {} file: {}", what, e))
+                .ok()
+        })
+        .unwrap_or_default()
+}
+
+/// Writes `value` as JSON to the data file at `file`, if any, logging
+/// failures under `what`.
+pub fn save_json<T: Serialize>(file: Option<&Path>, value: &T, what: &str) {
+    let Some(path) = file else {
+        return;
+    };
+    let result = serde_json::to_string(value)
+        .map_err(anyhow::Error::from)
+        .and_then(|data| write_atomic(path, &data));
+    if let Err(e) = result {
+        error!("Failed to save {}: {}", what, e);
+    }
+}
+
+/// Replaces the file at `path` with `data` by writing a temporary file
+/// first, so a crash never leaves it truncated. The temporary file is
+/// named after the process, so that several peyvand processes never write
+/// into the same one.
+pub fn write_atomic(path: &Path, data: &str) -> anyhow::Result<()> {
+    let name = path
+        .file_name()
+        .context("Cannot write to a path without a file name")?
+        .to_string_lossy();
+    let tmp = path.with_file_name(format!(".{name}.{}.tmp", std::process::id()));
+    std::fs::write(&tmp, data)?;
+    if let Err(e) = std::fs::rename(&tmp, path) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("peyvand").join(CONFIG_FILE))
+    }
+
+    /// Loads the config from `--config` or the default location. A missing
+    /// default file is not an error, a missing explicit one is.
+    pub fn load(args: &Args) -> anyhow::Result<Self> {
+        let path = match &args.config {
+            Some(path) => PathBuf::from(path),
+            None => match Self::default_path() {
+                Some(path) if path.exists() => path,
+                _ => {
+                    debug!("No config file found, using defaults");
+                    return Ok(Self::default());
+                }
+            },
+        };
+
+        info!("Loading config from {}", path.display());
+        let data = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        Self::parse(&data).with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    pub fn parse(data: &str) -> anyhow::Result<Self> {
+        let config: Self = toml::from_str(data)?;
+        let settings = &config.settings;
+        if let Some((name, _)) = settings
+            .search_engines
+            .iter()
+            .find(|(_, template)| !template.contains("{}"))
+        {
+            anyhow::bail!("Search engine {name} has no {{}} for the query");
+        }
+        if url::search_engine(&settings.search_engine, &settings.search_engines).is_none() {
+            anyhow::bail!("Unknown search-engine: {}", settings.search_engine);
+        }
+        hint::parse_alphabet(&settings.hint_alphabet).map_err(anyhow::Error::msg)?;
+        for pattern in settings.cookie_rules.keys() {
+            CookieRules::parse_pattern(pattern).map_err(anyhow::Error::msg)?;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            r#"
+[settings]
+homepage = "https://example.com"
+scroll-step = 80
+cookie-policy = "session"
+
+[bindings.normal]
+"J" = "scroll-down"
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.settings.homepage, "https://example.com");
+        assert_eq!(config.settings.scroll_step, 80);
+        assert_eq!(config.settings.cookie_policy, CookiePolicy::SessionOnly);
+        assert_eq!(config.keybindings.bindings["normal"]["J"], "scroll-down");
+    }
+
+    #[test]
+    fn test_parse_config_errors() {
+        assert!(Config::parse("[settings]\nscroll-step = \"fast\"").is_err());
+        assert!(Config::parse("[settings]\nhome-page = \"x\"").is_err());
+        assert!(Config::parse("[settings]\ncookie-policy = \"some\"").is_err());
+        assert!(Config::parse("[settings]\nsearch-engine = \"nope\"").is_err());
+        assert!(Config::parse("[settings.search-engines]\nx = \"https://x.org/\"").is_err());
+        assert!(Config::parse("[settings.cookie-rules]\n\"*.a.org\" = \"deny\"").is_err());
+        assert!(Config::parse("[settings.cookie-rules]\n\"a*.org\" = \"block\"").is_err());
+        assert!(Config::parse("[settings]\nhint-alphabet = \"aa\"").is_err());
+        assert!(Config::parse("").is_ok());
+    }
+
+    #[test]
+    fn test_parse_search_engines() {
+        let config = Config::parse(
+            r#"
+[settings]
+search-engine = "docs"
+
+[settings.search-engines]
+docs = "https://docs.rs/releases/search?query={}"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.settings.search_engine, "docs");
+
+        let mut settings = config.settings;
+        assert!(settings.set("search-engine", "gh").is_ok());
+        assert!(settings.set("search-engine", "nope").is_err());
+        assert_eq!(settings.search_engine, "gh");
+    }
+
+    #[test]
+    fn test_json_files() {
+        let dir = std::env::temp_dir().join(format!("peyvand-json-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.json");
+
+        let value = BTreeMap::from([("a".to_string(), 1)]);
+        save_json(Some(&path), &value, "data");
+        assert_eq!(
+            load_json::<BTreeMap<String, i32>>(Some(&path), "data"),
+            value
+        );
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        std::fs::write(&path, "{").unwrap();
+        assert!(load_json::<BTreeMap<String, i32>>(Some(&path), "data").is_empty());
+        assert!(load_json::<BTreeMap<String, i32>>(None, "data").is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+/* config.rs ends here */
@@ -19,47 +19,296 @@
 * along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use spdlog::error;
+use crate::config;
+use serde::{Deserialize, Serialize};
+use spdlog::{debug, error, warn};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::path::PathBuf;
-use wry::{cookie::Cookie, WebView};
+use strum_macros::{Display, EnumString};
+use wry::{
+    cookie::{time::OffsetDateTime, Cookie, Expiration},
+    http::Uri,
+    WebView,
+};
+
+/// First line of a Netscape cookie file, which curl checks for.
+const COOKIE_FILE_HEADER: &str = "# Netscape HTTP Cookie File";
+
+/// Prefix curl gives the domain of HttpOnly cookies, which the format has no
+/// field for.
+const HTTP_ONLY_PREFIX: &str = "#HttpOnly_";
+
+/// Subdomain asked for the cookies of a domain to tell host-only cookies,
+/// which it is not sent, from domain cookies.
+const SUBDOMAIN_PROBE: &str = "peyvand-probe";
 
 pub struct CookieManager {
     file: Option<PathBuf>,
     policy: CookiePolicy,
+    rules: CookieRules,
+    block_third_party: bool,
+    /// Sites of the pages opened in this session, whose cookies are first
+    /// party.
+    first_party: HashSet<String>,
 }
 
-#[derive(Clone, Copy)]
+/// Which cookies are accepted from domains without a [`CookieRule`].
+/// Session cookies are never written to the cookie file, they end with
+/// peyvand.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
 pub enum CookiePolicy {
     All,
     None,
+    #[serde(rename = "session")]
+    #[strum(serialize = "session")]
     SessionOnly,
 }
 
-impl CookieManager {
-    pub fn new(file: Option<String>, policy: Option<String>) -> Self {
-        let policy = match policy.as_deref() {
-            Some("all") => CookiePolicy::All,
-            Some("none") => CookiePolicy::None,
-            Some("session") => CookiePolicy::SessionOnly,
-            _ => CookiePolicy::All,
-        };
+/// What to do with the cookies of the domains a rule matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, EnumString, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum CookieRule {
+    Allow,
+    Block,
+    Session,
+}
 
+impl CookieRule {
+    fn policy(self) -> CookiePolicy {
+        match self {
+            CookieRule::Allow => CookiePolicy::All,
+            CookieRule::Block => CookiePolicy::None,
+            CookieRule::Session => CookiePolicy::SessionOnly,
+        }
+    }
+}
+
+/// Per-domain cookie rules, from the config and from `:cookies-allow` and
+/// `:cookies-block`, which are kept in `cookie-rules.json` and win over the
+/// configured ones.
+///
+/// A pattern is a domain, which matches only itself, `*.domain`, which
+/// matches the domain and all its subdomains, or `*` for every domain. The
+/// most specific matching pattern decides.
+pub struct CookieRules {
+    file: Option<PathBuf>,
+    configured: BTreeMap<String, CookieRule>,
+    saved: BTreeMap<String, CookieRule>,
+}
+
+impl CookieRules {
+    /// Loads the saved rules. `configured` must hold valid patterns.
+    pub fn load(file: Option<PathBuf>, configured: &BTreeMap<String, CookieRule>) -> Self {
+        let saved = config::load_json(file.as_deref(), "cookie rules");
+        let configured = configured
+            .iter()
+            .filter_map(|(pattern, rule)| Some((Self::parse_pattern(pattern).ok()?, *rule)))
+            .collect();
+        Self {
+            file,
+            configured,
+            saved,
+        }
+    }
+
+    /// Normalizes a domain pattern, rejecting anything but `*`, `*.domain`
+    /// and `domain`.
+    pub fn parse_pattern(pattern: &str) -> Result<String, String> {
+        let pattern = pattern.trim().trim_start_matches('.').to_ascii_lowercase();
+        let domain = pattern.strip_prefix("*.").unwrap_or(&pattern);
+        let valid = pattern == "*"
+            || domain.split('.').all(|label| {
+                !label.is_empty()
+                    && label
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            });
+        if valid {
+            Ok(pattern)
+        } else {
+            Err(format!("Invalid domain pattern: {pattern}"))
+        }
+    }
+
+    /// Sets the rule for `pattern` and saves it.
+    pub fn set(&mut self, pattern: &str, rule: CookieRule) -> Result<String, String> {
+        let pattern = Self::parse_pattern(pattern)?;
+        self.saved.insert(pattern.clone(), rule);
+        self.save();
+        Ok(pattern)
+    }
+
+    /// The rule of the most specific pattern matching `domain`.
+    pub fn rule(&self, domain: &str) -> Option<CookieRule> {
+        let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+        self.configured
+            .iter()
+            .chain(&self.saved)
+            .filter_map(|(pattern, rule)| Some((specificity(pattern, &domain)?, *rule)))
+            // On a tie the last one, a saved rule, wins.
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, rule)| rule)
+    }
+
+    fn save(&self) {
+        config::save_json(self.file.as_deref(), &self.saved, "cookie rules");
+    }
+}
+
+/// How specifically `pattern` matches `domain`, if at all.
+fn specificity(pattern: &str, domain: &str) -> Option<usize> {
+    if pattern == "*" {
+        return Some(0);
+    }
+    if let Some(parent) = pattern.strip_prefix("*.") {
+        let matches = domain == parent
+            || domain
+                .strip_suffix(parent)
+                .is_some_and(|sub| sub.ends_with('.'));
+        return matches.then_some(parent.len() + 1);
+    }
+    (pattern == domain).then_some(usize::MAX)
+}
+
+/// The host of a page, or `None` for pages like `about:blank`.
+pub fn host(url: &str) -> Option<String> {
+    let uri: Uri = url.parse().ok()?;
+    // `about:blank` parses as a host and port without a scheme.
+    uri.scheme()?;
+    let host = uri.host()?;
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// The site a host belongs to, taken as its last two labels. Without a
+/// public suffix list, hosts under suffixes like `co.uk` all count as one
+/// site, which errs on the side of keeping cookies.
+pub fn site(host: &str) -> String {
+    let host = host.trim_start_matches('.').to_ascii_lowercase();
+    let is_ip = host.contains(':') || host.split('.').all(|label| label.parse::<u8>().is_ok());
+    if is_ip {
+        return host;
+    }
+    let labels: Vec<&str> = host.rsplitn(3, '.').collect();
+    match labels.as_slice() {
+        [tld, name, _] => format!("{name}.{tld}"),
+        _ => host,
+    }
+}
+
+impl CookieManager {
+    pub fn new(
+        file: Option<String>,
+        policy: CookiePolicy,
+        rules: CookieRules,
+        block_third_party: bool,
+    ) -> Self {
         Self {
             file: file.map(PathBuf::from),
             policy,
+            rules,
+            block_third_party,
+            first_party: HashSet::new(),
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: CookiePolicy) {
+        self.policy = policy;
+    }
+
+    pub fn set_block_third_party(&mut self, block: bool) {
+        self.block_third_party = block;
+    }
+
+    pub fn set_rule(&mut self, pattern: &str, rule: CookieRule) -> Result<String, String> {
+        self.rules.set(pattern, rule)
+    }
+
+    /// Records a page opened in a tab, making its site first party.
+    pub fn visit(&mut self, url: &str) {
+        if let Some(host) = host(url) {
+            self.first_party.insert(site(&host));
+        }
+    }
+
+    /// Deletes the cookies of blocked domains and, when they are blocked,
+    /// third-party cookies that pages have set since.
+    pub fn enforce(&self, webview: &WebView) -> anyhow::Result<()> {
+        self.delete_where(webview, |cookie| {
+            cookie.domain().is_some_and(|domain| {
+                self.rules.rule(domain) == Some(CookieRule::Block) || self.is_third_party(domain)
+            })
+        })?;
+        Ok(())
+    }
+
+    /// The cookies of the webview's cookie store, grouped by domain.
+    pub fn cookies(
+        &self,
+        webview: &WebView,
+    ) -> anyhow::Result<BTreeMap<String, Vec<Cookie<'static>>>> {
+        Ok(group_by_domain(webview.cookies()?))
+    }
+
+    /// Deletes the cookie `name` of exactly `domain`, with `path` or with any
+    /// path when it is empty, and returns how many were deleted.
+    pub fn delete(
+        &self,
+        webview: &WebView,
+        domain: &str,
+        name: &str,
+        path: &str,
+    ) -> anyhow::Result<usize> {
+        let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+        self.delete_where(webview, |cookie| {
+            cookie_domain(cookie) == domain
+                && cookie.name() == name
+                && (path.is_empty() || cookie.path().unwrap_or("/") == path)
+        })
+    }
+
+    /// Deletes the cookies of `domain` and its subdomains, or all cookies
+    /// without one, and returns how many were deleted.
+    pub fn clear(&self, webview: &WebView, domain: Option<&str>) -> anyhow::Result<usize> {
+        let pattern = domain
+            .map(|domain| format!("*.{}", domain.trim_start_matches('.').to_ascii_lowercase()));
+        self.delete_where(webview, |cookie| match &pattern {
+            Some(pattern) => specificity(pattern, &cookie_domain(cookie)).is_some(),
+            None => true,
+        })
+    }
+
+    fn delete_where(
+        &self,
+        webview: &WebView,
+        predicate: impl Fn(&Cookie) -> bool,
+    ) -> anyhow::Result<usize> {
+        let mut deleted = 0;
+        for cookie in webview.cookies()? {
+            if predicate(&cookie) {
+                debug!(
+                    "Deleting cookie {} of {}",
+                    cookie.name(),
+                    cookie_domain(&cookie)
+                );
+                webview.delete_cookie(&cookie)?;
+                deleted += 1;
+            }
         }
+        Ok(deleted)
     }
 
     pub fn load_cookies(&self, webview: &WebView) -> anyhow::Result<()> {
         if let Some(path) = &self.file {
             if path.exists() {
                 let data = std::fs::read_to_string(path)?;
-                for line in data.lines() {
-                    if let Some((name, value)) = line.split_once('=') {
-                        let cookie = Cookie::new(name.trim(), value.trim());
-                        if self.accept(&cookie) {
-                            webview.set_cookie(&cookie)?;
-                        }
+                let now = OffsetDateTime::now_utc();
+                for StoredCookie { cookie, .. } in parse_cookie_file(&data) {
+                    let expired = cookie.expires_datetime().is_some_and(|time| time <= now);
+                    if !expired && self.accept(&cookie) {
+                        webview.set_cookie(&cookie)?;
                     }
                 }
             } else {
@@ -69,26 +318,456 @@ impl CookieManager {
         Ok(())
     }
 
+    /// Writes the persistent cookies of the domains whose cookies outlive
+    /// the session to the cookie file. Third-party cookies are left out when
+    /// they are blocked, so loading the file needs no list of visited sites.
     pub fn save_cookies(&self, webview: &WebView) -> anyhow::Result<()> {
-        if let Some(path) = &self.file {
-            let cookies = webview.cookies()?;
-            let mut content = String::new();
-            for cookie in cookies {
-                if self.accept(&cookie) {
-                    content.push_str(&format!("{}={}\n", cookie.name(), cookie.value()));
-                }
-            }
-            std::fs::write(path, content)?;
-        }
-        Ok(())
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let mut probes = HashMap::new();
+        let cookies: Vec<StoredCookie> = webview
+            .cookies()?
+            .into_iter()
+            .filter(|cookie| {
+                self.policy_for(cookie.domain()) == CookiePolicy::All
+                    && cookie.expires_datetime().is_some()
+                    && !cookie
+                        .domain()
+                        .is_some_and(|domain| self.is_third_party(domain))
+            })
+            .map(|cookie| StoredCookie {
+                host_only: is_host_only(webview, &mut probes, &cookie),
+                cookie,
+            })
+            .collect();
+        config::write_atomic(path, &format_cookie_file(&cookies))
+    }
+
+    /// The policy for the cookies of `domain`. Cookies from old cookie files
+    /// have no domain and follow the global policy.
+    fn policy_for(&self, domain: Option<&str>) -> CookiePolicy {
+        domain
+            .and_then(|domain| self.rules.rule(domain))
+            .map_or(self.policy, CookieRule::policy)
+    }
+
+    /// Whether the cookies of `domain` are blocked as third party: they
+    /// belong to no site opened in a tab this session and no rule allows
+    /// them. Which tab set a cookie is not known, so a site opened in one
+    /// tab is first party in all of them.
+    fn is_third_party(&self, domain: &str) -> bool {
+        self.block_third_party
+            && !self.first_party.contains(&site(domain))
+            && self.rules.rule(domain) != Some(CookieRule::Allow)
     }
 
     fn accept(&self, cookie: &Cookie) -> bool {
-        match self.policy {
+        match self.policy_for(cookie.domain()) {
             CookiePolicy::All => true,
             CookiePolicy::None => false,
-            CookiePolicy::SessionOnly => cookie.expires().is_none(),
+            CookiePolicy::SessionOnly => cookie.expires_datetime().is_none(),
+        }
+    }
+}
+
+/// Whether a cookie of the webview is only sent to the host that set it.
+/// The cookies wry returns do not say, but only domain cookies are also
+/// sent to a subdomain; when in doubt the cookie is taken as host-only.
+///
+/// `probes` keeps the cookies sent to a subdomain by domain and path, so
+/// that the cookie jar is asked once for all the cookies of a site.
+fn is_host_only(
+    webview: &WebView,
+    probes: &mut HashMap<(String, String), Vec<Cookie<'static>>>,
+    cookie: &Cookie,
+) -> bool {
+    let domain = cookie_domain(cookie);
+    let path = cookie.path().unwrap_or("/").to_string();
+    let sent = probes
+        .entry((domain, path))
+        .or_insert_with_key(|(domain, path)| {
+            webview
+                .cookies_for_url(&format!("https://{SUBDOMAIN_PROBE}.{domain}{path}"))
+                .unwrap_or_default()
+        });
+    !sent
+        .iter()
+        .any(|other| other.name() == cookie.name() && other.path() == cookie.path())
+}
+
+/// The domain of a cookie without its leading dot, or an empty string.
+fn cookie_domain(cookie: &Cookie) -> String {
+    cookie
+        .domain()
+        .unwrap_or_default()
+        .trim_start_matches('.')
+        .to_ascii_lowercase()
+}
+
+/// Groups cookies by domain, sorting them by name within each one.
+pub fn group_by_domain(cookies: Vec<Cookie<'static>>) -> BTreeMap<String, Vec<Cookie<'static>>> {
+    let mut groups: BTreeMap<String, Vec<Cookie>> = BTreeMap::new();
+    for cookie in cookies {
+        groups
+            .entry(cookie_domain(&cookie))
+            .or_default()
+            .push(cookie);
+    }
+    for cookies in groups.values_mut() {
+        cookies.sort_by(|a, b| (a.name(), a.path()).cmp(&(b.name(), b.path())));
+    }
+    groups
+}
+
+/// Describes a cookie for `:cookies`: its name and the length of its
+/// value, when it expires as seen from `now`, and its flags. The value
+/// itself is left out, as the page the list is shown in can read it.
+pub fn describe(cookie: &Cookie, now: OffsetDateTime) -> String {
+    let expiry = match cookie.expires_datetime() {
+        None => "session".to_string(),
+        Some(time) if time <= now => "expired".to_string(),
+        Some(time) => {
+            let seconds = (time - now).whole_seconds();
+            let left = match seconds {
+                86400.. => format!("{}d", seconds / 86400),
+                3600.. => format!("{}h", seconds / 3600),
+                60.. => format!("{}m", seconds / 60),
+                _ => format!("{seconds}s"),
+            };
+            format!("expires in {left}")
+        }
+    };
+    let size = match cookie.value().len() {
+        1 => "1 byte".to_string(),
+        len => format!("{len} bytes"),
+    };
+    let mut description = format!("{} ({})  {}", cookie.name(), size, expiry);
+    if let Some(path) = cookie.path().filter(|path| *path != "/") {
+        description.push_str(&format!("  path={path}"));
+    }
+    if cookie.secure() == Some(true) {
+        description.push_str("  Secure");
+    }
+    if cookie.http_only() == Some(true) {
+        description.push_str("  HttpOnly");
+    }
+    if let Some(same_site) = cookie.same_site() {
+        description.push_str(&format!("  SameSite={same_site}"));
+    }
+    description
+}
+
+/// A cookie of the cookie file, which unlike [`Cookie`] records whether
+/// it is host-only or also sent to subdomains.
+#[derive(Debug)]
+pub struct StoredCookie {
+    pub cookie: Cookie<'static>,
+    pub host_only: bool,
+}
+
+/// Formats cookies in the Netscape `cookies.txt` format read by curl and
+/// wget: one line per cookie with the tab-separated domain, whether
+/// subdomains match, path, Secure, expiry (0 for session cookies), name and
+/// value. Domain cookies have a leading dot. Cookies without a domain cannot
+/// be placed and are left out.
+pub fn format_cookie_file(cookies: &[StoredCookie]) -> String {
+    let mut content = format!("{COOKIE_FILE_HEADER}\n# Written by peyvand.\n\n");
+    for StoredCookie { cookie, host_only } in cookies {
+        let Some(domain) = cookie.domain() else {
+            continue;
+        };
+        let expires = cookie
+            .expires_datetime()
+            .map_or(0, |time| time.unix_timestamp().max(1));
+        let flag = |on: Option<bool>| if on == Some(true) { "TRUE" } else { "FALSE" };
+        content.push_str(&format!(
+            "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+            if cookie.http_only() == Some(true) {
+                HTTP_ONLY_PREFIX
+            } else {
+                ""
+            },
+            if *host_only { "" } else { "." },
+            domain,
+            flag(Some(!host_only)),
+            cookie.path().unwrap_or("/"),
+            flag(cookie.secure()),
+            expires,
+            cookie.name(),
+            cookie.value()
+        ));
+    }
+    content
+}
+
+/// Parses a Netscape `cookies.txt` file. Files from older versions, with a
+/// `name=value` line per cookie, are still read, but their cookies have no
+/// domain and apply to whichever page is loaded.
+pub fn parse_cookie_file(data: &str) -> Vec<StoredCookie> {
+    let mut cookies = Vec::new();
+    let mut legacy = false;
+    for line in data.lines() {
+        let line = line.trim_end_matches('\r');
+        let (line, http_only) = match line.strip_prefix(HTTP_ONLY_PREFIX) {
+            Some(line) => (line, true),
+            None => (line, false),
+        };
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        let cookie = match fields.as_slice() {
+            [domain, subdomains, path, secure, expires, name, value] => {
+                let Ok(expires) = expires.trim().parse::<i64>() else {
+                    warn!("Ignoring cookie with invalid expiry: {}", line);
+                    continue;
+                };
+                let expiration = match expires {
+                    0 => Expiration::Session,
+                    _ => match OffsetDateTime::from_unix_timestamp(expires) {
+                        Ok(time) => Expiration::DateTime(time),
+                        Err(_) => {
+                            warn!("Ignoring cookie with invalid expiry: {}", line);
+                            continue;
+                        }
+                    },
+                };
+                // Only a domain with a leading dot makes a domain cookie.
+                let host_only = !subdomains.eq_ignore_ascii_case("TRUE");
+                let domain = domain.trim_start_matches('.');
+                let domain = if host_only {
+                    domain.to_string()
+                } else {
+                    format!(".{domain}")
+                };
+                let cookie = Cookie::build((name.to_string(), value.to_string()))
+                    .domain(domain)
+                    .path(path.to_string())
+                    .secure(secure.eq_ignore_ascii_case("TRUE"))
+                    .http_only(http_only)
+                    .expires(expiration)
+                    .build();
+                StoredCookie { cookie, host_only }
+            }
+            [_] => match line.split_once('=') {
+                Some((name, value)) => {
+                    legacy = true;
+                    let cookie = Cookie::new(name.trim().to_string(), value.trim().to_string());
+                    StoredCookie {
+                        cookie,
+                        host_only: false,
+                    }
+                }
+                None => continue,
+            },
+            _ => {
+                warn!("Ignoring malformed cookie line: {}", line);
+                continue;
+            }
+        };
+        cookies.push(cookie);
+    }
+    if legacy {
+        warn!("Cookie file uses the old name=value format; it is rewritten on exit");
+    }
+    cookies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The attributes the cookie file keeps, for comparing cookies.
+    fn attributes(cookie: &Cookie) -> (String, String, String, String, bool, bool, Option<i64>) {
+        (
+            cookie.name().to_string(),
+            cookie.value().to_string(),
+            cookie.domain().unwrap_or_default().to_string(),
+            cookie.path().unwrap_or_default().to_string(),
+            cookie.secure().unwrap_or_default(),
+            cookie.http_only().unwrap_or_default(),
+            cookie.expires_datetime().map(|time| time.unix_timestamp()),
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let expires = OffsetDateTime::from_unix_timestamp(1_900_000_000).unwrap();
+        let stored = |cookie, host_only| StoredCookie { cookie, host_only };
+        let cookies = vec![
+            stored(
+                Cookie::build(("sid", "a=b; c"))
+                    .domain("example.com")
+                    .path("/app")
+                    .secure(true)
+                    .http_only(true)
+                    .expires(expires)
+                    .build(),
+                false,
+            ),
+            stored(
+                Cookie::build(("theme", "dark"))
+                    .domain("sub.example.org")
+                    .path("/")
+                    .expires(Expiration::Session)
+                    .build(),
+                false,
+            ),
+            stored(Cookie::build(("empty", "")).domain("a.org").build(), false),
+            stored(
+                Cookie::build(("login", "1"))
+                    .domain("www.example.net")
+                    .path("/")
+                    .build(),
+                true,
+            ),
+        ];
+        let text = format_cookie_file(&cookies);
+        assert!(text.starts_with(COOKIE_FILE_HEADER));
+        assert!(
+            text.contains("#HttpOnly_.example.com\tTRUE\t/app\tTRUE\t1900000000\tsid\ta=b; c\n")
+        );
+        assert!(text.contains(".sub.example.org\tTRUE\t/\tFALSE\t0\ttheme\tdark\n"));
+        assert!(text.contains("\nwww.example.net\tFALSE\t/\tFALSE\t0\tlogin\t1\n"));
+
+        let parsed = parse_cookie_file(&text);
+        assert_eq!(parsed.len(), 4);
+        for (parsed, cookie) in parsed.iter().zip(&cookies) {
+            assert_eq!(parsed.host_only, cookie.host_only);
         }
+        assert_eq!(
+            attributes(&parsed[0].cookie),
+            attributes(&cookies[0].cookie)
+        );
+        assert_eq!(
+            attributes(&parsed[1].cookie),
+            attributes(&cookies[1].cookie)
+        );
+        assert_eq!(parsed[2].cookie.path(), Some("/"));
+        assert_eq!(parsed[2].cookie.value(), "");
+        assert_eq!(
+            attributes(&parsed[3].cookie),
+            attributes(&cookies[3].cookie)
+        );
+    }
+
+    #[test]
+    fn test_parse_curl_file() {
+        let text = "# Netscape HTTP Cookie File\r\n\
+                    # https://curl.se/docs/http-cookies.html\r\n\
+                    \r\n\
+                    www.example.com\tFALSE\t/\tFALSE\t1900000000\tid\t42\r\n\
+                    bad line\twith tabs\r\n\
+                    .example.com\tTRUE\t/\tFALSE\tsoon\tx\ty\r\n";
+        let cookies = parse_cookie_file(text);
+        assert_eq!(cookies.len(), 1);
+        assert!(cookies[0].host_only);
+        let cookie = &cookies[0].cookie;
+        assert_eq!(cookie.domain(), Some("www.example.com"));
+        assert_eq!(cookie.name_value(), ("id", "42"));
+        assert_eq!(cookie.http_only(), Some(false));
+    }
+
+    #[test]
+    fn test_rules_pick_the_most_specific_pattern() {
+        let configured = BTreeMap::from([
+            ("*".to_string(), CookieRule::Session),
+            ("*.Example.com".to_string(), CookieRule::Block),
+            ("login.example.com".to_string(), CookieRule::Allow),
+        ]);
+        let mut rules = CookieRules::load(None, &configured);
+        assert_eq!(rules.rule("example.com"), Some(CookieRule::Block));
+        assert_eq!(rules.rule(".ads.example.com"), Some(CookieRule::Block));
+        assert_eq!(rules.rule("login.example.com"), Some(CookieRule::Allow));
+        assert_eq!(rules.rule("a.login.example.com"), Some(CookieRule::Block));
+        assert_eq!(rules.rule("notexample.com"), Some(CookieRule::Session));
+
+        assert_eq!(
+            rules.set(".EXAMPLE.com", CookieRule::Allow).unwrap(),
+            "example.com"
+        );
+        assert_eq!(rules.rule("example.com"), Some(CookieRule::Allow));
+        rules.set("*.example.com", CookieRule::Session).unwrap();
+        assert_eq!(rules.rule("ads.example.com"), Some(CookieRule::Session));
+
+        assert!(CookieRules::parse_pattern("a.*.com").is_err());
+        assert!(CookieRules::parse_pattern("*.").is_err());
+        assert!(CookieRules::parse_pattern("example..com").is_err());
+    }
+
+    #[test]
+    fn test_third_party_cookies() {
+        let rules = CookieRules::load(
+            None,
+            &BTreeMap::from([("*.cdn.net".to_string(), CookieRule::Allow)]),
+        );
+        let mut manager = CookieManager::new(None, CookiePolicy::All, rules, true);
+        manager.visit("https://www.example.com/page");
+        assert!(!manager.is_third_party("example.com"));
+        assert!(!manager.is_third_party("static.example.com"));
+        assert!(manager.is_third_party("tracker.org"));
+        assert!(!manager.is_third_party("img.cdn.net"));
+
+        manager.set_block_third_party(false);
+        assert!(!manager.is_third_party("tracker.org"));
+    }
+
+    #[test]
+    fn test_group_and_describe() {
+        let now = OffsetDateTime::from_unix_timestamp(1_800_000_000).unwrap();
+        let cookies = vec![
+            Cookie::build(("z", "1")).domain(".b.org").build(),
+            Cookie::build(("token", "x".repeat(40)))
+                .domain("a.org")
+                .path("/api")
+                .secure(true)
+                .http_only(true)
+                .same_site(wry::cookie::SameSite::Lax)
+                .expires(
+                    OffsetDateTime::from_unix_timestamp(1_800_000_000 + 3 * 86400 + 5).unwrap(),
+                )
+                .build(),
+            Cookie::build(("a", "2")).domain("b.org").build(),
+            Cookie::build(("old", "3"))
+                .domain("a.org")
+                .expires(OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap())
+                .build(),
+        ];
+        let groups = group_by_domain(cookies);
+        assert_eq!(groups.keys().collect::<Vec<_>>(), ["a.org", "b.org"]);
+        let names: Vec<_> = groups["b.org"].iter().map(Cookie::name).collect();
+        assert_eq!(names, ["a", "z"]);
+
+        let a = &groups["a.org"];
+        assert_eq!(describe(&a[0], now), "old (1 byte)  expired");
+        assert_eq!(
+            describe(&a[1], now),
+            "token (40 bytes)  expires in 3d  path=/api  Secure  HttpOnly  SameSite=Lax"
+        );
+        assert_eq!(describe(&groups["b.org"][0], now), "a (1 byte)  session");
+    }
+
+    #[test]
+    fn test_host_and_site() {
+        assert_eq!(
+            host("https://Www.Example.com:8080/a?b").as_deref(),
+            Some("www.example.com")
+        );
+        assert_eq!(host("about:blank"), None);
+        assert_eq!(site("a.b.example.com"), "example.com");
+        assert_eq!(site("localhost"), "localhost");
+        assert_eq!(site("192.168.1.10"), "192.168.1.10");
+    }
+
+    #[test]
+    fn test_parse_legacy_file() {
+        let cookies = parse_cookie_file("sid=abc\ntheme = dark\n\nnot a cookie\n");
+        let pairs: Vec<_> = cookies.iter().map(|c| c.cookie.name_value()).collect();
+        assert_eq!(pairs, vec![("sid", "abc"), ("theme", "dark")]);
+        assert_eq!(cookies[0].cookie.domain(), None);
+        // Without a domain they are not written back.
+        assert!(!format_cookie_file(&cookies).contains("sid"));
     }
 }
 
@@ -0,0 +1,176 @@
+/* download.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::{
+    event::{EventProxy, UserEvent},
+    tab::TabId,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use wry::WebView;
+
+/// What a webview reports about a download.
+#[derive(Debug)]
+pub enum DownloadEvent {
+    /// The download is being saved to a path.
+    Started(PathBuf),
+    /// The download ended, where it was saved and whether it succeeded.
+    Finished(Option<PathBuf>, bool),
+}
+
+/// Downloads started from hints. Each URL is loaded in a hidden webview,
+/// which shares the cookies of the tabs and, unlike a link in the page,
+/// is not held back by the same-origin rules for downloads.
+pub struct Downloads {
+    dir: Option<PathBuf>,
+    loaders: HashMap<TabId, Loader>,
+}
+
+struct Loader {
+    url: String,
+    started: bool,
+    // Dropping the webview would end the download.
+    _webview: WebView,
+}
+
+impl Downloads {
+    pub fn new() -> Self {
+        Self {
+            dir: dirs::download_dir().or_else(dirs::home_dir),
+            loaders: HashMap::new(),
+        }
+    }
+
+    /// Where downloads are saved, or `None` to let the webview decide.
+    pub fn dir(&self) -> Option<PathBuf> {
+        self.dir.clone()
+    }
+
+    pub fn add(&mut self, id: TabId, url: &str, webview: WebView) {
+        let loader = Loader {
+            url: url.to_string(),
+            started: false,
+            _webview: webview,
+        };
+        self.loaders.insert(id, loader);
+    }
+
+    pub fn contains(&self, id: TabId) -> bool {
+        self.loaders.contains_key(&id)
+    }
+
+    pub fn started(&mut self, id: TabId) {
+        if let Some(loader) = self.loaders.get_mut(&id) {
+            loader.started = true;
+        }
+    }
+
+    pub fn finished(&mut self, id: TabId) {
+        self.loaders.remove(&id);
+    }
+
+    /// Drops the loader of a URL that loaded as a page instead of starting
+    /// a download, and returns the URL.
+    pub fn loaded(&mut self, id: TabId) -> Option<String> {
+        if self.loaders.get(&id)?.started {
+            return None;
+        }
+        self.loaders.remove(&id).map(|loader| loader.url)
+    }
+}
+
+/// Saves downloads of a webview to `dir`, under the name the URL ends in.
+pub fn make_started_handler(
+    proxy: EventProxy,
+    id: TabId,
+    dir: Option<PathBuf>,
+) -> impl FnMut(String, &mut PathBuf) -> bool + 'static {
+    move |url: String, path: &mut PathBuf| {
+        if let Some(dir) = &dir {
+            *path = destination(dir, &url);
+        }
+        let event = DownloadEvent::Started(path.clone());
+        proxy.send_event(UserEvent::Download(id, event)).ok();
+        true
+    }
+}
+
+pub fn make_completed_handler(
+    proxy: EventProxy,
+    id: TabId,
+) -> impl Fn(String, Option<PathBuf>, bool) + 'static {
+    move |_url: String, path: Option<PathBuf>, success: bool| {
+        let event = DownloadEvent::Finished(path, success);
+        proxy.send_event(UserEvent::Download(id, event)).ok();
+    }
+}
+
+/// A path in `dir` that does not exist yet for the file `url` points to,
+/// numbered like `file-1.txt` when the name is taken.
+pub fn destination(dir: &Path, url: &str) -> PathBuf {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let name = path
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty() && *name != "." && *name != "..")
+        .unwrap_or("download");
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{extension}")),
+        _ => (name, String::new()),
+    };
+    std::iter::once(dir.join(name))
+        .chain((1..).map(|i| dir.join(format!("{stem}-{i}{extension}"))))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_destination() {
+        let dir = std::env::temp_dir().join(format!("peyvand-downloads-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = destination(&dir, "https://example.com/files/report.pdf?x=1#top");
+        assert_eq!(path, dir.join("report.pdf"));
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(
+            destination(&dir, "https://example.com/report.pdf"),
+            dir.join("report-1.pdf")
+        );
+        assert_eq!(
+            destination(&dir, "https://example.com/"),
+            dir.join("download")
+        );
+        assert_eq!(
+            destination(&dir, "https://example.com/a/.."),
+            dir.join("download")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+/* download.rs ends here */
@@ -0,0 +1,53 @@
+/* event.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::{action::Action, download::DownloadEvent, history::SameDocument, tab::TabId};
+use tao::event_loop::EventLoopProxy;
+
+/// Events sent to the event loop from webview handlers.
+#[derive(Debug)]
+pub enum UserEvent {
+    /// An action together with its count prefix.
+    Action(Action, usize),
+    /// A tab started loading a page.
+    LoadStarted(TabId, String),
+    /// A tab finished loading a page, whose final URL may differ after
+    /// redirects.
+    LoadFinished(TabId, String),
+    /// A page asked to open a URL in a new window.
+    NewWindow(String),
+    /// The user followed a link or submitted a form in a tab.
+    UserNavigation(TabId),
+    /// The URL of a tab changed without loading a new document.
+    SameDocument(TabId, SameDocument, String),
+    /// The document title of a tab changed.
+    TitleChanged(TabId, String),
+    /// The page in a tab was scrolled to an offset.
+    Scrolled(TabId, f64, f64),
+    /// A tab needs labels for this many hints.
+    Hints(TabId, usize),
+    /// A download of a tab or of a hinted URL progressed.
+    Download(TabId, DownloadEvent),
+}
+
+pub type EventProxy = EventLoopProxy<UserEvent>;
+
+/* event.rs ends here */
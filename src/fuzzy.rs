@@ -0,0 +1,69 @@
+/* fuzzy.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/// Scores how well `pattern` matches `text` as a case-insensitive
+/// subsequence, or returns `None` if it does not match at all. Consecutive
+/// characters and matches at the start of a word score higher.
+pub fn score(pattern: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+
+    for p in pattern.chars().flat_map(char::to_lowercase) {
+        let found = position + text[position..].iter().position(|&c| c == p)?;
+        score += 1;
+        if previous.is_some_and(|prev| prev + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        position = found + 1;
+    }
+    // Prefer shorter texts among equally good matches.
+    Some(score * 1000 - text.len() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        assert!(score("rl", "rust-lang.org").is_some());
+        assert!(score("RUST", "rust-lang.org").is_some());
+        assert!(score("xyz", "rust-lang.org").is_none());
+        assert!(score("gnal", "rust-lang.org").is_none());
+        assert!(score("", "anything").is_some());
+
+        let consecutive = score("lang", "rust-lang.org").unwrap();
+        let scattered = score("lang", "lots-and-nothing-good").unwrap();
+        assert!(consecutive > scattered);
+
+        let short = score("rust", "rust.org").unwrap();
+        let long = score("rust", "rust.org/learn/get-started").unwrap();
+        assert!(short > long);
+    }
+}
+
+/* fuzzy.rs ends here */
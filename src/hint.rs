@@ -19,54 +19,482 @@
 * along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
+use strum_macros::{AsRefStr, Display, EnumIter, EnumString};
+
+/// Keys hint labels are made of unless configured otherwise: the home row.
+pub const DEFAULT_HINT_ALPHABET: &str = "asdfghjkl";
+
+/// The elements a hint mode labels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, AsRefStr, Display, EnumIter, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum HintTarget {
+    #[default]
+    All,
+    Links,
+    Inputs,
+    Images,
+}
+
+/// What following a hint does. The page acts on most of them itself and
+/// sends the rest back in a `hinted:` message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, AsRefStr, Display, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum HintAction {
+    /// Clicks the element, or focuses it if it takes text.
+    #[default]
+    Follow,
+    /// Opens the link in a new window.
+    Window,
+    YankUrl,
+    YankText,
+    Download,
+    Focus,
+    Hover,
+    RightClick,
+    /// Opens the link with the `external-command`.
+    External,
+    /// Opens links in background tabs, hinting again after each one.
+    Background,
+    /// Selects links until Enter opens them all in background tabs.
+    SelectOpen,
+    /// Selects links until Enter yanks them all.
+    SelectYank,
+}
+
 const HINT_JS: &str = r#"
 
-window.hintState = {
+const hintState = {
   active: false,
+  action: "follow",
+  alphabet: "",
+  selector: "",
   hints: [],
-  buffer: ""
+  // The hints left by the text filter, which the labels belong to.
+  matching: [],
+  buffer: "",
+  filter: "",
+  filtering: false,
+  // URLs picked in the select modes.
+  selected: []
+};
+
+const isSelecting = () => hintState.action.startsWith("select-");
+
+const hintStatus = () => {
+  const state = hintState;
+  let status = "Hint";
+  if (isSelecting()) status += `  ${state.selected.length} selected`;
+  if (state.filtering) status += "  /" + state.filter;
+  return status;
+};
+
+const hintSelectors = {
+  all: [
+    "a[href]", "area[href]", "button", "input:not([type='hidden'])", "select", "textarea",
+    "summary", "label[for]", "video[controls]", "audio[controls]",
+    "[contenteditable='']", "[contenteditable='true']", "[onclick]", "[onmousedown]",
+    "[tabindex]:not([tabindex='-1'])",
+    ...["button", "link", "checkbox", "radio", "tab", "menuitem", "option", "switch",
+        "combobox", "textbox"].map(role => `[role='${role}']`),
+  ].join(", "),
+  links: "a[href], area[href], [role='link']",
+  inputs: "input:not([type='hidden']), textarea, select, [contenteditable=''], [contenteditable='true'], [role='textbox']",
+  images: "img",
 };
 
-window.showHints = function() {
-  window.clearHints();
-  window.hintState.active = true;
-  window.hintState.buffer = "";
+// Where an element shows in the top viewport, or null if it is hidden or
+// outside `frame`, the visible part of the frame it is in.
+const visibleRect = (element, frame) => {
+  const style = element.ownerDocument.defaultView.getComputedStyle(element);
+  if (style.visibility !== "visible" || style.display === "none") return null;
+  // Links around a block element, such as an image, have no size themselves.
+  const rects = [...element.getClientRects()];
+  if (element.firstElementChild) rects.push(...element.firstElementChild.getClientRects());
+  for (const rect of rects) {
+    if (rect.width < 1 || rect.height < 1) continue;
+    const left = frame.x + rect.left;
+    const top = frame.y + rect.top;
+    if (left + rect.width <= frame.left || top + rect.height <= frame.top
+        || left >= frame.right || top >= frame.bottom) continue;
+    return { left: Math.max(left, frame.left), top: Math.max(top, frame.top) };
+  }
+  return null;
+};
 
-  const links = document.querySelectorAll("a, button, [role='link']");
-  let index = 0;
+// Collects the visible elements matching `selector` in a document or shadow
+// root, descending into open shadow roots and same-origin frames.
+const collectHints = (root, selector, frame, found) => {
+  for (const element of root.querySelectorAll("*")) {
+    if (element.matches(selector) && !element.disabled) {
+      const rect = visibleRect(element, frame);
+      if (rect) found.push({ element, rect });
+    }
+    if (element.shadowRoot) collectHints(element.shadowRoot, selector, frame, found);
+    if (element.tagName === "IFRAME" || element.tagName === "FRAME") {
+      let doc = null;
+      try {
+        doc = element.contentDocument;
+      } catch (e) {
+        // Cross-origin frames are out of reach.
+      }
+      if (!doc || !doc.documentElement) continue;
+      const box = element.getBoundingClientRect();
+      const x = frame.x + box.left + element.clientLeft;
+      const y = frame.y + box.top + element.clientTop;
+      const inner = {
+        x,
+        y,
+        left: Math.max(frame.left, x),
+        top: Math.max(frame.top, y),
+        right: Math.min(frame.right, x + element.clientWidth),
+        bottom: Math.min(frame.bottom, y + element.clientHeight),
+      };
+      if (inner.left < inner.right && inner.top < inner.bottom) {
+        collectHints(doc, selector, inner, found);
+      }
+    }
+  }
+  return found;
+};
 
-  links.forEach(link => {
-    const label = index.toString(36); // base36 (0-9, a-z) short labels
-    index++;
+// Drops hints on elements inside another hinted element of the same size,
+// like a button in a link.
+const withoutDuplicates = (found) => {
+  const hinted = new Set(found.map(({ element }) => element));
+  return found.filter(({ element, rect }) => {
+    for (let parent = element.parentElement; parent; parent = parent.parentElement) {
+      if (!hinted.has(parent)) continue;
+      const outer = found.find(other => other.element === parent).rect;
+      return outer.left !== rect.left || outer.top !== rect.top;
+    }
+    return true;
+  });
+};
 
-    const rect = link.getBoundingClientRect();
-    if (rect.width === 0 && rect.height === 0) return;
+let hintLayer = null;
+let hintObserver = null;
+let rebuildTimer = null;
 
-    const hint = document.createElement("div");
-    hint.className = "pey-hint";
-    hint.innerText = label.toUpperCase();
-    hint.style.position = "absolute";
-    hint.style.left = `${window.scrollX + rect.left}px`;
-    hint.style.top = `${window.scrollY + rect.top}px`;
-    hint.style.background = "red";
-    hint.style.color = "white";
-    hint.style.fontSize = "12px";
-    hint.style.fontWeight = "bold";
-    hint.style.padding = "2px 4px";
-    hint.style.borderRadius = "3px";
-    hint.style.zIndex = 99999;
+// Markers and our own layer do not count as changes to the page.
+const ownMutation = (mutation) =>
+  (hintLayer && hintLayer.contains(mutation.target))
+  || (mutation.type === "childList"
+      && [...mutation.addedNodes, ...mutation.removedNodes].every(node => node === hintLayer));
 
-    document.body.appendChild(hint);
+const scheduleRebuild = () => {
+  if (!hintState.active) return;
+  clearTimeout(rebuildTimer);
+  rebuildTimer = setTimeout(rebuildHints, 150);
+};
 
-    window.hintState.hints.push({ label: label.toUpperCase(), link, hint });
+// Hints the elements visible now, keeping the text filter. Elements still
+// there keep their hint, and while no new ones show up they also keep
+// their labels and the keys typed so far.
+const rebuildHints = () => {
+  const state = hintState;
+  if (!state.active) return;
+  const viewport = {
+    x: 0, y: 0, left: 0, top: 0, right: window.innerWidth, bottom: window.innerHeight,
+  };
+  const found = withoutDuplicates(collectHints(document, state.selector, viewport, []));
+  const previous = new Map(state.hints.map(hint => [hint.element, hint]));
+  const appeared = found.some(({ element }) => !previous.has(element));
+  state.hints = found.map(({ element, rect }) => {
+    const hint = previous.get(element) || {
+      element,
+      url: hintUrl(element),
+      text: hintFilterText(element),
+      label: "",
+      marker: null,
+    };
+    previous.delete(element);
+    hint.rect = rect;
+    return hint;
   });
+  for (const gone of previous.values()) {
+    if (gone.marker) gone.marker.remove();
+  }
+  if (appeared || !state.hints.length) {
+    filterHints();
+    return;
+  }
+  state.matching = state.matching.filter(hint => state.hints.includes(hint));
+  state.hints.forEach(placeMarker);
+  renderHints();
+};
+
+const placeMarker = (hint) => {
+  if (!hint.marker) return;
+  hint.marker.style.left = `${hint.rect.left}px`;
+  hint.marker.style.top = `${hint.rect.top}px`;
+  hint.marker.style.background = hintState.selected.includes(hint.url) ? "green" : "red";
+};
+
+// The text a hint can be filtered by.
+const hintFilterText = (element) =>
+  [hintText(element), element.title, element.getAttribute("aria-label")]
+    .filter(Boolean).join(" ").toLowerCase();
+
+// Collects the elements to hint and asks peyvand for their labels, which
+// arrive through `labelHints`.
+exposeEntryPoint("showHints", (action, target, alphabet) => {
+  clearHints();
+  const state = hintState;
+  state.active = true;
+  state.action = action || "follow";
+  state.alphabet = alphabet || "";
+  state.selector = hintSelectors[target] || hintSelectors.all;
+
+  hintLayer = document.createElement("div");
+  hintLayer.className = "pey-hints";
+  Object.assign(hintLayer.style, {
+    position: "fixed",
+    left: "0",
+    top: "0",
+    width: "0",
+    height: "0",
+    zIndex: 2147483647,
+  });
+  document.documentElement.appendChild(hintLayer);
+
+  hintObserver = new MutationObserver(mutations => {
+    if (!mutations.every(ownMutation)) scheduleRebuild();
+  });
+  // Attribute changes, which carousels and clocks make all the time, are
+  // left out; the hints follow the layout on scroll and resize.
+  hintObserver.observe(document, { childList: true, subtree: true });
+  window.addEventListener("scroll", scheduleRebuild, true);
+  window.addEventListener("resize", scheduleRebuild);
+  rebuildHints();
+});
+
+// Keeps the hints whose text contains every typed word and asks for new
+// labels for them. A filter that leaves one hint follows it.
+const filterHints = () => {
+  const state = hintState;
+  const words = state.filter.toLowerCase().split(/\s+/).filter(Boolean);
+  state.matching = state.hints.filter(hint => words.every(word => hint.text.includes(word)));
+  state.buffer = "";
+  updateStatus(hintStatus());
+  if (words.length && state.matching.length === 1) {
+    followHint(state.matching[0]);
+    return;
+  }
+  sendAction("hints:" + state.matching.length);
+};
+
+exposeEntryPoint("labelHints", (labels) => {
+  const state = hintState;
+  // Labels for an outdated filter; newer ones are on their way.
+  if (!state.active || labels.length !== state.matching.length) return;
+  for (const hint of state.hints) hint.label = "";
+  state.matching.forEach((hint, i) => {
+    hint.label = labels[i];
+    if (!hint.marker) hint.marker = createMarker();
+    placeMarker(hint);
+  });
+  renderHints();
+});
+
+const createMarker = () => {
+  const marker = document.createElement("div");
+  marker.className = "pey-hint";
+  Object.assign(marker.style, {
+    position: "absolute",
+    whiteSpace: "nowrap",
+    color: "white",
+    fontSize: "12px",
+    fontWeight: "bold",
+    fontFamily: "monospace",
+    padding: "2px 4px",
+    borderRadius: "3px",
+    zIndex: 99999,
+  });
+  hintLayer.appendChild(marker);
+  return marker;
+};
+
+// Shows the hints whose label starts with the typed keys, dimming the part
+// already typed.
+const renderHints = () => {
+  const buffer = hintState.buffer;
+  for (const hint of hintState.hints) {
+    if (!hint.marker) continue;
+    const visible = hint.label !== "" && hint.label.startsWith(buffer);
+    hint.marker.style.display = visible ? "block" : "none";
+    if (!visible) continue;
+    const typed = document.createElement("span");
+    typed.style.opacity = "0.5";
+    typed.textContent = buffer.toUpperCase();
+    hint.marker.replaceChildren(typed, hint.label.slice(buffer.length).toUpperCase());
+  }
+};
+
+// Adds a key to the typed label. Keys no label continues with are ignored.
+const typeLabel = (key) => {
+  const state = hintState;
+  const buffer = state.buffer + key.toLowerCase();
+  const matching = state.matching.filter(hint => hint.label.startsWith(buffer));
+  if (!matching.length) return;
+  state.buffer = buffer;
+  const match = matching.find(hint => hint.label === buffer);
+  if (match) {
+    followHint(match);
+  } else {
+    renderHints();
+  }
+};
+
+// Handles a key in hint mode. Keys of the label alphabet type labels, any
+// other character starts filtering the hints by their text, and Tab
+// switches between the two. Enter follows the first hint left.
+const hintKeydown = (e) => {
+  const state = hintState;
+  const key = e.key;
+  if (key === "Tab") {
+    state.filtering = !state.filtering;
+    state.buffer = "";
+    updateStatus(hintStatus());
+    renderHints();
+  } else if (key === "Enter" && isSelecting()) {
+    if (state.selected.length) {
+      sendAction("hinted:" + state.action + ":" + state.selected.join("\n"));
+    }
+    leaveHints("Normal");
+  } else if (key === "Enter") {
+    const first = state.matching.find(hint => hint.label.startsWith(state.buffer));
+    if (first) followHint(first);
+  } else if (key === "Backspace") {
+    if (state.filtering) {
+      state.filter = state.filter.slice(0, -1);
+      filterHints();
+    } else {
+      state.buffer = state.buffer.slice(0, -1);
+      renderHints();
+    }
+  } else if ([...key].length === 1 && !e.ctrlKey && !e.metaKey && !e.altKey) {
+    if (!state.filtering && state.alphabet.includes(key.toLowerCase())) {
+      typeLabel(key);
+    } else {
+      state.filtering = true;
+      state.filter += key;
+      filterHints();
+    }
+  }
+};
+
+const isEditable = (element) =>
+  element.isContentEditable || ["INPUT", "TEXTAREA", "SELECT"].includes(element.tagName);
+
+// The URL a hinted element points to: its link, or its image source.
+const hintUrl = (element) => {
+  const link = element.closest("a[href], area[href]");
+  if (link) return link.href;
+  return element.currentSrc || element.src || "";
+};
+
+const hintText = (element) =>
+  (element.innerText || element.value || element.alt || element.title
+    || element.getAttribute("aria-label") || "").trim();
+
+const dispatchMouse = (element, types, button) => {
+  const rect = element.getBoundingClientRect();
+  const init = {
+    bubbles: true,
+    cancelable: true,
+    view: window,
+    button,
+    clientX: rect.left + rect.width / 2,
+    clientY: rect.top + rect.height / 2,
+  };
+  for (const type of types) element.dispatchEvent(new MouseEvent(type, init));
 };
 
-window.clearHints = function() {
-  window.hintState.hints.forEach(h => h.hint.remove());
-  window.hintState.hints = [];
-  window.hintState.active = false;
-  window.hintState.buffer = "";
+const leaveHints = (mode) => {
+  clearHints();
+  appState.mode = mode;
+  sendAction(mode === "Insert" ? "insert-mode" : "normal-mode");
+  updateStatus(mode);
+};
+
+// Opens or selects the link of a hint in the sticky modes, which then hint
+// the page again.
+const pickHint = (hint) => {
+  const state = hintState;
+  if (!hint.url) {
+    showMessage("No link there", true);
+  } else if (state.action === "background") {
+    sendAction("hinted:background:" + hint.url);
+  } else {
+    const index = state.selected.indexOf(hint.url);
+    if (index < 0) {
+      state.selected.push(hint.url);
+    } else {
+      state.selected.splice(index, 1);
+    }
+  }
+  state.filter = "";
+  state.filtering = false;
+  filterHints();
+};
+
+const followHint = (hint) => {
+  const element = hint.element;
+  const action = hintState.action;
+  if (action === "background" || isSelecting()) {
+    pickHint(hint);
+    return;
+  }
+  // Peyvand only takes a hinted URL or text while the hints are up.
+  if (action === "yank-text") {
+    sendAction("hinted:yank-text:" + hintText(element));
+  } else if (!["follow", "focus", "hover", "right-click"].includes(action)) {
+    const url = hintUrl(element);
+    if (!url) {
+      leaveHints("Normal");
+      showMessage("Nothing to " + action.replace("-", " ") + " there", true);
+      return;
+    }
+    sendAction("hinted:" + action + ":" + url);
+  }
+  const focus = action === "focus" || (action === "follow" && isEditable(element));
+  leaveHints(focus ? "Insert" : "Normal");
+
+  switch (action) {
+    case "follow":
+    case "focus":
+      if (focus) {
+        element.focus();
+      } else {
+        reportUserNavigation(element);
+        element.click();
+      }
+      break;
+    case "hover":
+      dispatchMouse(element, ["mouseover", "mouseenter", "mousemove"], 0);
+      break;
+    case "right-click":
+      dispatchMouse(element, ["mousedown", "mouseup", "contextmenu"], 2);
+      break;
+  }
+};
+
+const clearHints = () => {
+  const state = hintState;
+  if (hintObserver) hintObserver.disconnect();
+  window.removeEventListener("scroll", scheduleRebuild, true);
+  window.removeEventListener("resize", scheduleRebuild);
+  clearTimeout(rebuildTimer);
+  if (hintLayer) hintLayer.remove();
+  hintObserver = null;
+  hintLayer = null;
+  state.hints = [];
+  state.matching = [];
+  state.active = false;
+  state.buffer = "";
+  state.filter = "";
+  state.filtering = false;
+  state.selected = [];
 };
 "#;
 
@@ -78,4 +506,123 @@ impl Hint {
     }
 }
 
+/// Parses the keys hint labels are made of: at least two distinct
+/// characters, matched case-insensitively.
+pub fn parse_alphabet(alphabet: &str) -> Result<Vec<char>, String> {
+    let mut keys: Vec<char> = Vec::new();
+    for c in alphabet.chars().flat_map(char::to_lowercase) {
+        if c.is_whitespace() || c.is_control() || keys.contains(&c) {
+            return Err(format!("Invalid hint alphabet: {alphabet}"));
+        }
+        keys.push(c);
+    }
+    if keys.len() < 2 {
+        return Err(format!("Hint alphabet needs two keys or more: {alphabet}"));
+    }
+    Ok(keys)
+}
+
+/// Generates `count` labels from `alphabet`, none of which is a prefix of
+/// another, so every hint can be typed and is followed on its last key.
+///
+/// Uniform labels all have the same length. Otherwise the labels form a
+/// balanced tree, like Huffman codes of equally likely hints: their lengths
+/// differ by at most one and the first hints get the short ones.
+pub fn labels(count: usize, alphabet: &[char], uniform: bool) -> Vec<String> {
+    let base = alphabet.len();
+    if count == 0 || base < 2 {
+        return Vec::new();
+    }
+    if uniform {
+        let mut length = 1;
+        let mut capacity = base;
+        while capacity < count {
+            length += 1;
+            capacity = capacity.saturating_mul(base);
+        }
+        // The first key varies fastest, so that it narrows the hints most.
+        return (0..count)
+            .map(|mut index| {
+                (0..length)
+                    .map(|_| {
+                        let c = alphabet[index % base];
+                        index /= base;
+                        c
+                    })
+                    .collect()
+            })
+            .collect();
+    }
+
+    // Breadth-first, turn the shortest label into a node with a child per
+    // key until there are enough leaves.
+    let mut labels = vec![String::new()];
+    let mut leaves = 0;
+    while labels.len() - leaves < count || labels.len() == 1 {
+        let parent = labels[leaves].clone();
+        leaves += 1;
+        labels.extend(alphabet.iter().map(|c| format!("{parent}{c}")));
+    }
+    labels.drain(..leaves);
+    labels.truncate(count);
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_prefix_free(labels: &[String]) {
+        for (i, a) in labels.iter().enumerate() {
+            for (j, b) in labels.iter().enumerate() {
+                assert!(
+                    i == j || !b.starts_with(a.as_str()),
+                    "{a} is a prefix of {b}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_balanced_labels() {
+        let alphabet = parse_alphabet(DEFAULT_HINT_ALPHABET).unwrap();
+        assert_eq!(labels(0, &alphabet, false), Vec::<String>::new());
+        assert_eq!(labels(3, &alphabet, false), ["a", "s", "d"]);
+        assert_eq!(
+            labels(10, &alphabet, false),
+            ["s", "d", "f", "g", "h", "j", "k", "l", "aa", "as"]
+        );
+        for count in [1, 9, 10, 81, 82, 500, 1000] {
+            let labels = labels(count, &alphabet, false);
+            assert_eq!(labels.len(), count);
+            assert_prefix_free(&labels);
+            let min = labels.iter().map(String::len).min().unwrap();
+            let max = labels.iter().map(String::len).max().unwrap();
+            assert!(max - min <= 1, "{count}: lengths {min} to {max}");
+            assert!(labels
+                .iter()
+                .all(|l| l.chars().all(|c| alphabet.contains(&c))));
+        }
+    }
+
+    #[test]
+    fn test_uniform_labels() {
+        let alphabet = parse_alphabet("ab").unwrap();
+        assert_eq!(labels(2, &alphabet, true), ["a", "b"]);
+        assert_eq!(labels(3, &alphabet, true), ["aa", "ba", "ab"]);
+        let alphabet = parse_alphabet(DEFAULT_HINT_ALPHABET).unwrap();
+        let labels = labels(100, &alphabet, true);
+        assert!(labels.iter().all(|label| label.len() == 3));
+        assert_prefix_free(&labels);
+    }
+
+    #[test]
+    fn test_parse_alphabet() {
+        assert_eq!(parse_alphabet("AsD").unwrap(), ['a', 's', 'd']);
+        assert!(parse_alphabet("a").is_err());
+        assert!(parse_alphabet("asa").is_err());
+        assert!(parse_alphabet("a s").is_err());
+    }
+}
+
 /* hint.rs ends here */
@@ -19,50 +19,252 @@
 * along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-#[derive(Debug)]
+use crate::{config, fuzzy};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use strum::EnumString;
+
+const HISTORY_JS: &str = r#"
+const reportUserNavigation = (element) => {
+  const link = element.closest("a[href], area[href]");
+  if (!link || link.hasAttribute("download")) {
+    return;
+  }
+  if (link.target && link.target.toLowerCase() !== "_self") {
+    return;
+  }
+  const url = new URL(link.href, document.baseURI);
+  const here = window.location;
+  if (url.hash && url.origin + url.pathname + url.search === here.origin + here.pathname + here.search) {
+    return;
+  }
+  sendAction("user-navigation");
+};
+
+(() => {
+  const report = (change) => sendAction("location:" + change + ":" + window.location.href);
+  const pushState = history.pushState;
+  const replaceState = history.replaceState;
+  history.pushState = function(...args) {
+    const result = pushState.apply(this, args);
+    report("push");
+    return result;
+  };
+  history.replaceState = function(...args) {
+    const result = replaceState.apply(this, args);
+    report("replace");
+    return result;
+  };
+  window.addEventListener("popstate", () => report("pop"));
+  window.addEventListener("hashchange", () => report("pop"));
+  // Only events the user caused count: a page that clicks its own links
+  // or submits its own forms is redirecting.
+  window.addEventListener("click", (e) => {
+    if (!e.isTrusted || e.defaultPrevented || e.button !== 0) {
+      return;
+    }
+    if (e.ctrlKey || e.shiftKey || e.altKey || e.metaKey) {
+      return;
+    }
+    const target = e.composedPath()[0];
+    if (target instanceof Element) {
+      reportUserNavigation(target);
+    }
+  });
+  window.addEventListener("submit", (e) => {
+    if (e.isTrusted && !e.defaultPrevented) {
+      sendAction("user-navigation");
+    }
+  });
+})();
+"#;
+
+/// How a tab arrived at its current entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Navigation {
+    /// A new page, opened by the user or by following a link.
+    User,
+    /// The page being loaded sent the tab elsewhere before it finished; the
+    /// new URL replaces its entry.
+    Redirect,
+    /// A move through the back/forward list to the entry at this index.
+    Traversal(usize),
+}
+
+/// A URL change that did not load a new document, reported by the
+/// injected script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum SameDocument {
+    /// `history.pushState`.
+    Push,
+    /// `history.replaceState`.
+    Replace,
+    /// `popstate` or `hashchange`: a traversal, or following an anchor.
+    Pop,
+}
+
+/// How the webview should carry out a traversal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Traversal {
+    /// Move through the webview's own back/forward list.
+    Steps(isize),
+    /// The entry only exists in a restored session; load it in place of the
+    /// current page.
+    Replace(String),
+}
+
+/// The back/forward list of a tab, kept in step with the webview by feeding
+/// it the page-load events instead of guessing from navigation requests.
+///
+/// Entries restored from a session are not known to the webview, so only
+/// the `live` range of entries can be reached with `history.go`.
+#[derive(Debug, Clone)]
 pub struct History {
     stack: Vec<String>,
     current_index: usize,
+    pending: Option<Navigation>,
+    loading: bool,
+    live: (usize, usize),
 }
 
 impl Default for History {
     fn default() -> Self {
         let initial_url = "https://mr.katebzadeh.xyz";
-        Self {
-            stack: vec![initial_url.to_string()],
-            current_index: 0,
-        }
+        Self::new(initial_url)
     }
 }
 impl History {
     pub fn new(initial_url: &str) -> Self {
+        Self::restore(vec![initial_url.to_string()], 0)
+    }
+
+    /// Recreates a saved back/forward list whose current entry is about to
+    /// be loaded into a fresh webview.
+    pub fn restore(mut stack: Vec<String>, index: usize) -> Self {
+        if stack.is_empty() {
+            stack.push("about:blank".to_string());
+        }
+        let index = index.min(stack.len() - 1);
         Self {
-            stack: vec![initial_url.to_string()],
-            current_index: 0,
+            stack,
+            current_index: index,
+            pending: None,
+            loading: false,
+            live: (index, index),
         }
     }
 
-    pub fn push(&mut self, url: &str) {
-        self.stack.truncate(self.current_index + 1);
-        self.stack.push(url.to_string());
-        self.current_index += 1;
+    pub fn entries(&self) -> &[String] {
+        &self.stack
+    }
+
+    pub fn index(&self) -> usize {
+        self.current_index
+    }
+
+    pub fn get_js() -> &'static str {
+        HISTORY_JS
+    }
+
+    /// Announces that the user asked for the next load, so that it is not
+    /// mistaken for a redirect of a page that is still loading.
+    pub fn expect_user_navigation(&mut self) {
+        self.pending = Some(Navigation::User);
     }
 
-    pub fn back(&mut self) -> Option<&str> {
-        if self.current_index > 0 {
-            self.current_index -= 1;
-            self.stack.get(self.current_index).map(|s| s.as_str())
+    /// Prepares a traversal of up to `steps` entries and returns how the
+    /// webview should carry it out, or `None` if there is nowhere to go.
+    pub fn go(&mut self, steps: isize) -> Option<Traversal> {
+        let target =
+            (self.current_index as isize + steps).clamp(0, self.stack.len() as isize - 1) as usize;
+        if target == self.current_index {
+            return None;
+        }
+        self.pending = Some(Navigation::Traversal(target));
+        let (first, last) = self.live;
+        if (first..=last).contains(&target) {
+            Some(Traversal::Steps(
+                target as isize - self.current_index as isize,
+            ))
         } else {
-            None
+            // Replacing the page leaves the webview with no usable entries
+            // around the target.
+            self.live = (target, target);
+            Some(Traversal::Replace(self.stack[target].clone()))
         }
     }
 
-    pub fn forward(&mut self) -> Option<&str> {
-        if self.current_index + 1 < self.stack.len() {
-            self.current_index += 1;
-            self.stack.get(self.current_index).map(|s| s.as_str())
-        } else {
-            None
+    /// Handles the start of a page load and returns how it was classified.
+    pub fn load_started(&mut self, url: &str) -> Navigation {
+        let navigation = match self.pending.take() {
+            Some(navigation) => navigation,
+            None if self.loading => Navigation::Redirect,
+            None => Navigation::User,
+        };
+        self.apply(navigation, url);
+        self.loading = true;
+        navigation
+    }
+
+    /// Handles the end of a page load; server-side redirects only show up
+    /// here, as a URL that differs from the one that started loading.
+    pub fn load_finished(&mut self, url: &str) {
+        self.loading = false;
+        if !url.is_empty() {
+            self.stack[self.current_index] = url.to_string();
+        }
+    }
+
+    /// Handles a URL change within the current document and returns how it
+    /// was classified, or `None` if the URL did not change.
+    pub fn same_document(&mut self, change: SameDocument, url: &str) -> Option<Navigation> {
+        if url == self.current() && change != SameDocument::Push {
+            return None;
+        }
+        let navigation = match change {
+            SameDocument::Push => Navigation::User,
+            SameDocument::Replace => Navigation::Redirect,
+            SameDocument::Pop => match self.pending.take() {
+                Some(Navigation::Traversal(target)) => Navigation::Traversal(target),
+                _ => self
+                    .neighbour(url)
+                    .map_or(Navigation::User, Navigation::Traversal),
+            },
+        };
+        self.apply(navigation, url);
+        Some(navigation)
+    }
+
+    /// The index of an adjacent entry with this URL, for traversals the page
+    /// started on its own.
+    fn neighbour(&self, url: &str) -> Option<usize> {
+        let index = self.current_index;
+        [index.checked_sub(1), Some(index + 1)]
+            .into_iter()
+            .flatten()
+            .find(|&i| self.stack.get(i).is_some_and(|entry| entry == url))
+    }
+
+    fn apply(&mut self, navigation: Navigation, url: &str) {
+        match navigation {
+            // Reloads start a load of the current URL.
+            Navigation::User if url == self.current() => {}
+            Navigation::User => {
+                self.stack.truncate(self.current_index + 1);
+                self.stack.push(url.to_string());
+                self.current_index += 1;
+                self.live = (self.live.0.min(self.current_index), self.current_index);
+            }
+            Navigation::Redirect => self.stack[self.current_index] = url.to_string(),
+            Navigation::Traversal(target) => {
+                self.current_index = target.min(self.stack.len() - 1);
+                self.stack[self.current_index] = url.to_string();
+            }
         }
     }
 
@@ -71,4 +273,419 @@ impl History {
     }
 }
 
+/// A page in the global history. Timestamps are seconds since the epoch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub url: String,
+    pub title: String,
+    pub visit_count: u32,
+    pub first_visit: u64,
+    pub last_visit: u64,
+}
+
+impl HistoryEntry {
+    /// How often and how recently the page was visited: the visit count
+    /// weighted by the age of the last visit.
+    pub fn frecency(&self, now: u64) -> u64 {
+        let weight = match now.saturating_sub(self.last_visit) / (24 * 60 * 60) {
+            0..=3 => 100,
+            4..=14 => 70,
+            15..=31 => 50,
+            32..=90 => 30,
+            _ => 10,
+        };
+        u64::from(self.visit_count) * weight
+    }
+}
+
+/// Every page visited in any tab, persisted across sessions.
+///
+/// Changes are written by `flush`, which peyvand calls on the autosave tick
+/// and on exit, rather than on every visit.
+pub struct HistoryStore {
+    file: Option<PathBuf>,
+    entries: HashMap<String, HistoryEntry>,
+    dirty: bool,
+}
+
+impl HistoryStore {
+    pub fn load(file: Option<PathBuf>) -> Self {
+        let entries: Vec<HistoryEntry> = config::load_json(file.as_deref(), "history");
+        Self {
+            file,
+            entries: entries
+                .into_iter()
+                .map(|entry| (entry.url.clone(), entry))
+                .collect(),
+            dirty: false,
+        }
+    }
+
+    /// Records a visit to `url`; pages that are not on the web or on disk
+    /// are not remembered.
+    pub fn record_visit(&mut self, url: &str) {
+        self.record_visit_at(url, now());
+    }
+
+    fn record_visit_at(&mut self, url: &str, time: u64) {
+        if !["http://", "https://", "file://"]
+            .iter()
+            .any(|scheme| url.starts_with(scheme))
+        {
+            return;
+        }
+        let entry = self
+            .entries
+            .entry(url.to_string())
+            .or_insert_with(|| HistoryEntry {
+                url: url.to_string(),
+                title: String::new(),
+                visit_count: 0,
+                first_visit: time,
+                last_visit: time,
+            });
+        entry.visit_count += 1;
+        entry.last_visit = time;
+        self.dirty = true;
+    }
+
+    pub fn set_title(&mut self, url: &str, title: &str) {
+        if let Some(entry) = self.entries.get_mut(url) {
+            if entry.title != title {
+                entry.title = title.to_string();
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// All entries, most recently visited first.
+    pub fn entries(&self) -> Vec<&HistoryEntry> {
+        let mut entries: Vec<_> = self.entries.values().collect();
+        entries.sort_by(|a, b| b.last_visit.cmp(&a.last_visit).then(a.url.cmp(&b.url)));
+        entries
+    }
+
+    /// Entries whose URL starts with `prefix`, also when the scheme and a
+    /// leading `www.` are left out, most visited first.
+    pub fn query_prefix(&self, prefix: &str) -> Vec<&HistoryEntry> {
+        let mut matches: Vec<_> = self
+            .entries
+            .values()
+            .filter(|entry| {
+                entry.url.starts_with(prefix) || strip_scheme(&entry.url).starts_with(prefix)
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            (b.visit_count, b.last_visit)
+                .cmp(&(a.visit_count, a.last_visit))
+                .then(a.url.cmp(&b.url))
+        });
+        matches
+    }
+
+    /// Entries whose URL or title fuzzily match `pattern`, best match first.
+    pub fn query_fuzzy(&self, pattern: &str) -> Vec<&HistoryEntry> {
+        let mut matches: Vec<_> = self
+            .entries
+            .values()
+            .filter_map(|entry| {
+                let score =
+                    fuzzy::score(pattern, &entry.url).max(fuzzy::score(pattern, &entry.title))?;
+                Some((score, entry))
+            })
+            .collect();
+        matches.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .cmp(a_score)
+                .then(b.last_visit.cmp(&a.last_visit))
+                .then(a.url.cmp(&b.url))
+        });
+        matches.into_iter().map(|(_, entry)| entry).collect()
+    }
+
+    /// Forgets pages not visited in the last `days` days and returns how many
+    /// were removed.
+    pub fn prune(&mut self, days: u64) -> usize {
+        let removed = self.prune_before(now().saturating_sub(days * 24 * 60 * 60));
+        self.dirty |= removed > 0;
+        removed
+    }
+
+    fn prune_before(&mut self, cutoff: u64) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| entry.last_visit >= cutoff);
+        before - self.entries.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.dirty = true;
+        self.flush();
+    }
+
+    /// Saves the history if it changed since it was last saved.
+    pub fn flush(&mut self) {
+        if self.dirty {
+            self.save();
+            self.dirty = false;
+        }
+    }
+
+    fn save(&self) {
+        config::save_json(self.file.as_deref(), &self.entries(), "history");
+    }
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn strip_scheme(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.strip_prefix("www.").unwrap_or(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(urls: &[&str]) -> History {
+        let mut history = History::new(urls[0]);
+        for url in urls {
+            history.load_started(url);
+            history.load_finished(url);
+        }
+        history
+    }
+
+    #[test]
+    fn test_user_navigations() {
+        let mut history = history(&["https://a.org/", "https://b.org/"]);
+        assert_eq!(history.stack, vec!["https://a.org/", "https://b.org/"]);
+        assert_eq!(history.current(), "https://b.org/");
+
+        // A reload does not add an entry.
+        assert_eq!(history.load_started("https://b.org/"), Navigation::User);
+        history.load_finished("https://b.org/");
+        assert_eq!(history.stack.len(), 2);
+    }
+
+    #[test]
+    fn test_traversals_keep_forward_entries() {
+        let mut history = history(&["https://a.org/", "https://b.org/", "https://c.org/"]);
+
+        assert_eq!(history.go(-2), Some(Traversal::Steps(-2)));
+        assert_eq!(
+            history.load_started("https://a.org/"),
+            Navigation::Traversal(0)
+        );
+        history.load_finished("https://a.org/");
+        assert_eq!(history.current(), "https://a.org/");
+        assert_eq!(history.stack.len(), 3);
+
+        assert_eq!(history.go(5), Some(Traversal::Steps(2)));
+        history.load_started("https://c.org/");
+        assert_eq!(history.current(), "https://c.org/");
+
+        assert_eq!(history.go(1), None);
+        assert_eq!(history.pending, None);
+    }
+
+    #[test]
+    fn test_restored_entries_are_replaced() {
+        let urls = ["https://a.org/", "https://b.org/", "https://c.org/"];
+        let mut history = History::restore(urls.map(String::from).to_vec(), 2);
+        history.load_started("https://c.org/");
+        history.load_finished("https://c.org/");
+
+        let back = history.go(-1);
+        assert_eq!(back, Some(Traversal::Replace("https://b.org/".to_string())));
+        history.load_started("https://b.org/");
+        history.load_finished("https://b.org/");
+        assert_eq!(history.index(), 1);
+        assert_eq!(history.entries().len(), 3);
+
+        // Pages opened from here on are known to the webview.
+        history.load_started("https://d.org/");
+        history.load_finished("https://d.org/");
+        assert_eq!(history.go(-1), Some(Traversal::Steps(-1)));
+        history.load_started("https://b.org/");
+        assert_eq!(
+            history.go(-1),
+            Some(Traversal::Replace("https://a.org/".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_redirects_replace_entries() {
+        let mut history = history(&["https://a.org/"]);
+
+        // A client-side redirect before the first page finished loading.
+        history.load_started("https://b.org/");
+        assert_eq!(history.load_started("https://c.org/"), Navigation::Redirect);
+        // A server-side redirect only visible once the load finishes.
+        history.load_finished("https://d.org/");
+        assert_eq!(history.stack, vec!["https://a.org/", "https://d.org/"]);
+
+        // A new URL entered by the user while a page is still loading.
+        history.load_started("https://e.org/");
+        history.expect_user_navigation();
+        assert_eq!(history.load_started("https://f.org/"), Navigation::User);
+        assert_eq!(history.stack.len(), 4);
+    }
+
+    #[test]
+    fn test_clicks_while_loading() {
+        let mut history = history(&["https://a.org/"]);
+
+        // The page sends itself elsewhere while still loading.
+        history.load_started("https://b.org/");
+        assert_eq!(history.load_started("https://c.org/"), Navigation::Redirect);
+
+        // The user clicks a link on the page before it finished loading.
+        history.expect_user_navigation();
+        assert_eq!(history.load_started("https://d.org/"), Navigation::User);
+        assert_eq!(
+            history.stack,
+            vec!["https://a.org/", "https://c.org/", "https://d.org/"]
+        );
+        history.load_finished("https://d.org/");
+        assert_eq!(history.current(), "https://d.org/");
+    }
+
+    #[test]
+    fn test_same_document_changes() {
+        let mut history = history(&["https://a.org/"]);
+
+        let push = history.same_document(SameDocument::Push, "https://a.org/#x");
+        assert_eq!(push, Some(Navigation::User));
+        let replace = history.same_document(SameDocument::Replace, "https://a.org/#y");
+        assert_eq!(replace, Some(Navigation::Redirect));
+        assert_eq!(history.stack, vec!["https://a.org/", "https://a.org/#y"]);
+
+        // Back through a popstate started by the page itself.
+        let pop = history.same_document(SameDocument::Pop, "https://a.org/");
+        assert_eq!(pop, Some(Navigation::Traversal(0)));
+        // The hashchange that follows the popstate.
+        assert_eq!(
+            history.same_document(SameDocument::Pop, "https://a.org/"),
+            None
+        );
+
+        // An anchor that is not next to the current entry.
+        let anchor = history.same_document(SameDocument::Pop, "https://a.org/#z");
+        assert_eq!(anchor, Some(Navigation::User));
+        assert_eq!(history.stack, vec!["https://a.org/", "https://a.org/#z"]);
+    }
+
+    fn store(visits: &[(&str, u64)]) -> HistoryStore {
+        let mut store = HistoryStore::load(None);
+        for (url, time) in visits {
+            store.record_visit_at(url, *time);
+        }
+        store
+    }
+
+    #[test]
+    fn test_frecency() {
+        let day = 24 * 60 * 60;
+        let entry = |visit_count, last_visit| HistoryEntry {
+            url: "https://a.org/".to_string(),
+            title: String::new(),
+            visit_count,
+            first_visit: 0,
+            last_visit,
+        };
+        let now = 1000 * day;
+        assert_eq!(entry(3, now).frecency(now), 300);
+        assert_eq!(entry(3, now - 10 * day).frecency(now), 210);
+        assert_eq!(entry(3, now - 365 * day).frecency(now), 30);
+        // Frequent visits long ago lose to a few recent ones.
+        assert!(entry(20, 0).frecency(now) < entry(3, now).frecency(now));
+    }
+
+    #[test]
+    fn test_record_visits() {
+        let mut store = store(&[
+            ("https://a.org/", 10),
+            ("https://b.org/", 20),
+            ("https://a.org/", 30),
+            ("about:blank", 40),
+        ]);
+        store.set_title("https://a.org/", "A");
+
+        let a = store.entries.get("https://a.org/").unwrap();
+        assert_eq!((a.visit_count, a.first_visit, a.last_visit), (2, 10, 30));
+        assert_eq!(a.title, "A");
+        assert!(!store.entries.contains_key("about:blank"));
+
+        let urls: Vec<_> = store.entries().iter().map(|e| e.url.as_str()).collect();
+        assert_eq!(urls, vec!["https://a.org/", "https://b.org/"]);
+    }
+
+    #[test]
+    fn test_changes_are_saved_on_flush() {
+        let path =
+            std::env::temp_dir().join(format!("peyvand-history-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut store = HistoryStore::load(Some(path.clone()));
+        store.record_visit("https://a.org/");
+        store.set_title("https://a.org/", "A");
+        assert!(!path.exists());
+
+        store.flush();
+        let loaded = HistoryStore::load(Some(path.clone()));
+        assert_eq!(loaded.entries().len(), 1);
+        assert_eq!(loaded.entries()[0].title, "A");
+        std::fs::remove_file(&path).unwrap();
+
+        // Nothing changed since, so nothing is written.
+        store.flush();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_queries() {
+        let store = store(&[
+            ("https://www.rust-lang.org/learn", 10),
+            ("https://www.rust-lang.org/", 20),
+            ("https://www.rust-lang.org/", 30),
+            ("https://docs.rs/", 40),
+        ]);
+
+        let urls = |entries: Vec<&HistoryEntry>| -> Vec<String> {
+            entries.iter().map(|e| e.url.clone()).collect()
+        };
+        assert_eq!(
+            urls(store.query_prefix("rust")),
+            vec![
+                "https://www.rust-lang.org/",
+                "https://www.rust-lang.org/learn"
+            ]
+        );
+        assert_eq!(
+            urls(store.query_prefix("https://docs")),
+            vec!["https://docs.rs/"]
+        );
+        assert!(store.query_prefix("docs.rs/x").is_empty());
+
+        assert_eq!(
+            urls(store.query_fuzzy("rslearn")),
+            vec!["https://www.rust-lang.org/learn"]
+        );
+        assert_eq!(urls(store.query_fuzzy("docs"))[0], "https://docs.rs/");
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut store = store(&[("https://old.org/", 10), ("https://new.org/", 100)]);
+        assert_eq!(store.prune_before(50), 1);
+        assert!(!store.entries.contains_key("https://old.org/"));
+        assert!(store.entries.contains_key("https://new.org/"));
+    }
+}
+
 /* history.rs ends here */
@@ -0,0 +1,165 @@
+/* ipc.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use spdlog::warn;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use wry::http::Request;
+
+/// Schemes of pages that are allowed to talk to peyvand.
+const TRUSTED_SCHEMES: &[&str] = &["http", "https", "file", "about"];
+
+/// Authenticates messages posted by peyvand's injected scripts.
+///
+/// Page scripts share the `window` of the injected code and can call
+/// `window.ipc.postMessage` themselves, so every message is prefixed with a
+/// per-session token that only lives inside the closure of the injected code.
+#[derive(Clone)]
+pub struct Ipc {
+    token: String,
+}
+
+impl Ipc {
+    pub fn new() -> Self {
+        let state = RandomState::new();
+        let token = (0..2u64)
+            .map(|i| {
+                let mut hasher = state.build_hasher();
+                hasher.write_u64(i);
+                format!("{:016x}", hasher.finish())
+            })
+            .collect();
+        Self { token }
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Wraps the injected scripts in a closure holding the token and a
+    /// private `sendAction`, which only posts from the top-level frame.
+    ///
+    /// The state of the injected scripts stays inside the closure too. The
+    /// functions peyvand calls are defined with `lockEntryPoint`, so pages
+    /// cannot replace them, or with `exposeEntryPoint`, which also ignores
+    /// calls that do not pass the token first. Overlays showing anything
+    /// beyond the page itself are put in a closed shadow root by
+    /// `mountOverlay`, out of reach of the page's scripts.
+    pub fn wrap_script(&self, js: &str) -> String {
+        format!(
+            r#"(() => {{
+const PEYVAND_TOKEN = "{token}";
+const postMessage = window.ipc.postMessage;
+const sendAction = (cmd) => {{
+  if (window.top === window) postMessage(PEYVAND_TOKEN + ":" + cmd);
+}};
+const defineProperty = Object.defineProperty;
+const reflectApply = Reflect.apply;
+const arraySlice = Array.prototype.slice;
+const lockEntryPoint = (name, fn) => {{
+  defineProperty(window, name, {{ value: fn, writable: false, configurable: false }});
+}};
+const exposeEntryPoint = (name, fn) => {{
+  lockEntryPoint(name, function(token) {{
+    if (token !== PEYVAND_TOKEN) return;
+    return reflectApply(fn, null, reflectApply(arraySlice, arguments, [1]));
+  }});
+}};
+const attachShadow = Element.prototype.attachShadow;
+const mountOverlay = (element) => {{
+  const host = document.createElement("div");
+  reflectApply(attachShadow, host, [{{ mode: "closed" }}]).appendChild(element);
+  (document.body || document.documentElement).appendChild(host);
+  return host;
+}};
+{js}
+}})();"#,
+            token = self.token
+        )
+    }
+
+    /// Returns the message without its token if it was posted by the injected
+    /// scripts of a page with a trusted scheme.
+    pub fn verify<'a>(&self, req: &'a Request<String>) -> Option<&'a str> {
+        let scheme = req.uri().scheme_str().unwrap_or_default();
+        if !TRUSTED_SCHEMES.contains(&scheme) {
+            warn!("Rejected IPC message from {}", req.uri());
+            return None;
+        }
+
+        let body = req.body();
+        let verified = body
+            .split_once(':')
+            .filter(|(token, _)| constant_time_eq(token.as_bytes(), self.token.as_bytes()))
+            .map(|(_, msg)| msg);
+        if verified.is_none() {
+            warn!("Rejected unauthenticated IPC message from {}", req.uri());
+        }
+        verified
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(uri: &str, body: &str) -> Request<String> {
+        Request::builder().uri(uri).body(body.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_tokens_differ_per_session() {
+        let ipc = Ipc::new();
+        assert_eq!(ipc.token().len(), 32);
+        assert_ne!(ipc.token(), Ipc::new().token());
+    }
+
+    #[test]
+    fn test_verify() {
+        let ipc = Ipc::new();
+        let token = ipc.token().to_string();
+
+        let req = request(
+            "https://example.com/",
+            &format!("{token}:change-url:https://a.b"),
+        );
+        assert_eq!(ipc.verify(&req), Some("change-url:https://a.b"));
+
+        assert_eq!(ipc.verify(&request("https://example.com/", "exit")), None);
+        assert_eq!(
+            ipc.verify(&request("https://example.com/", "change-url:x")),
+            None
+        );
+        let last = if token.ends_with('0') { '1' } else { '0' };
+        let forged = format!("{}{last}:exit", &token[..31]);
+        assert_eq!(ipc.verify(&request("https://example.com/", &forged)), None);
+        assert_eq!(
+            ipc.verify(&request("custom://evil/", &format!("{token}:exit"))),
+            None
+        );
+    }
+}
+
+/* ipc.rs ends here */
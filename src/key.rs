@@ -19,7 +19,7 @@
 * along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use crate::action::Action;
+use crate::{action::Action, hint::HintTarget, script};
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::str::FromStr;
@@ -29,6 +29,7 @@ use strum::{AsRefStr, EnumIter, EnumString};
 #[derive(
     AsRefStr, Default, Clone, Copy, Debug, EnumIter, EnumString, Display, PartialEq, Eq, Hash,
 )]
+#[strum(ascii_case_insensitive)]
 pub enum KeyMode {
     #[default]
     Normal,
@@ -38,14 +39,171 @@ pub enum KeyMode {
     Hint,
 }
 
+/// Vim key names and the DOM `KeyboardEvent.key` values they stand for.
+const NAMED_KEYS: &[(&str, &str)] = &[
+    ("Esc", "Escape"),
+    ("CR", "Enter"),
+    ("Tab", "Tab"),
+    ("BS", "Backspace"),
+    ("Space", " "),
+    ("lt", "<"),
+    ("Up", "ArrowUp"),
+    ("Down", "ArrowDown"),
+    ("Left", "ArrowLeft"),
+    ("Right", "ArrowRight"),
+    ("Del", "Delete"),
+    ("Insert", "Insert"),
+    ("Home", "Home"),
+    ("End", "End"),
+    ("PageUp", "PageUp"),
+    ("PageDown", "PageDown"),
+];
+
+/// Alternative spellings accepted when parsing.
+const KEY_ALIASES: &[(&str, &str)] = &[
+    ("Escape", "Esc"),
+    ("Enter", "CR"),
+    ("Return", "CR"),
+    ("BackSpace", "BS"),
+    ("Delete", "Del"),
+];
+
+/// A single key press in Vim notation, e.g. `j`, `<C-d>` or `<S-Tab>`.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct KeySequence(pub Vec<String>);
+pub struct Key {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub meta: bool,
+    pub shift: bool,
+    /// A single character, or a key name from `NAMED_KEYS` or `F1`-`F24`.
+    pub name: String,
+}
 
-impl KeySequence {
-    pub fn from_str(s: &str) -> Self {
-        Self(s.chars().map(|c| c.to_string()).collect())
+impl Key {
+    fn named(name: &str) -> Option<String> {
+        let name = KEY_ALIASES
+            .iter()
+            .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+            .map_or(name, |(_, canonical)| canonical);
+        if let Some((canonical, _)) = NAMED_KEYS
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        {
+            return Some(canonical.to_string());
+        }
+        let n: u8 = name.strip_prefix(['F', 'f'])?.parse().ok()?;
+        (1..=24).contains(&n).then(|| format!("F{n}"))
+    }
+
+    /// Whether the key produces a character, so that Shift is already
+    /// reflected in the key itself (`A` rather than `<S-a>`).
+    fn is_printable(&self) -> bool {
+        self.name.chars().count() == 1 || self.name == "lt" || self.name == "Space"
+    }
+
+    fn from_char(c: char) -> Self {
+        let name = match c {
+            '<' => "lt".to_string(),
+            ' ' => "Space".to_string(),
+            c => c.to_string(),
+        };
+        Self {
+            ctrl: false,
+            alt: false,
+            meta: false,
+            shift: false,
+            name,
+        }
+    }
+
+    /// Parses the inside of `<...>`, e.g. `C-d` or `S-Tab`.
+    fn from_notation(inner: &str) -> Result<Self, String> {
+        let mut key = Self::from_char(' ');
+        let mut rest = inner;
+        while rest.chars().count() > 2 && rest.as_bytes()[1] == b'-' {
+            match rest.as_bytes()[0].to_ascii_uppercase() {
+                b'C' => key.ctrl = true,
+                b'A' => key.alt = true,
+                b'M' => key.meta = true,
+                b'S' => key.shift = true,
+                _ => return Err(format!("Unknown modifier in <{inner}>")),
+            }
+            rest = &rest[2..];
+        }
+
+        let mut chars = rest.chars();
+        key.name = match (chars.next(), chars.next()) {
+            (Some(c), None) => Self::from_char(c).name,
+            _ => Self::named(rest).ok_or_else(|| format!("Unknown key <{inner}>"))?,
+        };
+
+        if key.shift && key.is_printable() {
+            key.shift = false;
+            if key.name.chars().count() == 1 {
+                key.name = key.name.to_uppercase();
+            }
+        }
+        Ok(key)
+    }
+}
+
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut mods = String::new();
+        for (on, m) in [
+            (self.ctrl, "C-"),
+            (self.alt, "A-"),
+            (self.meta, "M-"),
+            (self.shift, "S-"),
+        ] {
+            if on {
+                mods.push_str(m);
+            }
+        }
+        if mods.is_empty() && self.name.chars().count() == 1 {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "<{}{}>", mods, self.name)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeySequence(pub Vec<Key>);
+
+impl FromStr for KeySequence {
+    type Err = String;
+
+    /// Parses Vim notation such as `gt`, `<C-d>` or `<S-Tab>x`. A `<` that
+    /// does not start a `<...>` group is taken literally.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut keys = Vec::new();
+        let mut rest = s;
+        while let Some(c) = rest.chars().next() {
+            if c == '<' {
+                if let Some(end) = rest[1..].find('>').map(|i| i + 1).filter(|&i| i > 1) {
+                    keys.push(Key::from_notation(&rest[1..end])?);
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+            keys.push(Key::from_char(c));
+            rest = &rest[c.len_utf8()..];
+        }
+        if keys.is_empty() {
+            return Err("Empty key sequence".to_string());
+        }
+        Ok(Self(keys))
+    }
+}
+
+impl std::fmt::Display for KeySequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.iter().try_for_each(|key| write!(f, "{key}"))
     }
+}
 
+impl KeySequence {
     pub fn is_prefix_of(&self, other: &KeySequence) -> bool {
         if self.0.len() >= other.0.len() {
             return false;
@@ -54,13 +212,33 @@ impl KeySequence {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct KeybindingConfig {
+    #[serde(default)]
     pub bindings: HashMap<String, HashMap<String, String>>,
 }
 
+/// Commands that take the key pressed after their binding as argument, like
+/// Vim's `m{a-z}`.
+fn key_argument_commands() -> Vec<String> {
+    [
+        Action::MarkSet(String::new()),
+        Action::MarkJump(String::new()),
+        Action::QuickmarkSet(String::new()),
+        Action::QuickmarkOpen(String::new()),
+    ]
+    .iter()
+    .map(Action::to_string)
+    .collect()
+}
+
+fn default_seq(s: &str) -> KeySequence {
+    s.parse().expect("default bindings use valid key notation")
+}
+
 pub struct KeybindingManager {
     bindings: HashMap<KeyMode, HashMap<String, KeySequence>>,
+    dirty: bool,
 }
 
 impl KeybindingManager {
@@ -68,76 +246,152 @@ impl KeybindingManager {
         let mut bindings: HashMap<KeyMode, HashMap<String, KeySequence>> = HashMap::new();
 
         let mut normal = HashMap::new();
-        normal.insert(Action::ScrollDown.to_string(), KeySequence::from_str("j"));
-        normal.insert(Action::ScrollUp.to_string(), KeySequence::from_str("k"));
-        normal.insert(Action::ScrollTop.to_string(), KeySequence::from_str("gt"));
+        normal.insert(Action::ScrollDown.to_string(), default_seq("j"));
+        normal.insert(Action::ScrollUp.to_string(), default_seq("k"));
+        normal.insert(Action::ScrollTop.to_string(), default_seq("gg"));
+        normal.insert(Action::ScrollBottom.to_string(), default_seq("gb"));
+        normal.insert(Action::ScrollHalfDown.to_string(), default_seq("<C-d>"));
+        normal.insert(Action::ScrollHalfUp.to_string(), default_seq("<C-u>"));
+        normal.insert(Action::GoBack.to_string(), default_seq("h"));
+        normal.insert(Action::GoForward.to_string(), default_seq("l"));
+        normal.insert(Action::InsertMode.to_string(), default_seq("i"));
+        normal.insert(Action::CmdMode.to_string(), default_seq(":"));
+        normal.insert(Action::HintMode.to_string(), default_seq("f"));
+        let hint_actions = [
+            (Action::HintWindow(HintTarget::Links), ";w"),
+            (Action::HintYankUrl(HintTarget::Links), ";y"),
+            (Action::HintYankText(HintTarget::All), ";Y"),
+            (Action::HintDownload(HintTarget::Links), ";d"),
+            (Action::HintFocus(HintTarget::Inputs), "gi"),
+            (Action::HintHover(HintTarget::All), ";h"),
+            (Action::HintRightClick(HintTarget::All), ";r"),
+            (Action::HintExternal(HintTarget::Links), ";x"),
+            (Action::HintBackground(HintTarget::Links), ";b"),
+            (Action::HintSelectOpen(HintTarget::Links), ";m"),
+            (Action::HintSelectYank(HintTarget::Links), ";M"),
+        ];
+        for (action, seq) in hint_actions {
+            normal.insert(action.to_string(), default_seq(seq));
+        }
+
+        normal.insert(Action::ShowHelp.to_string(), default_seq("?"));
+        normal.insert(Action::ShowURL.to_string(), default_seq("o"));
+        normal.insert(Action::HardRefreshURL.to_string(), default_seq("R"));
+        normal.insert(Action::SoftRefreshURL.to_string(), default_seq("r"));
+        normal.insert(Action::CopyURL.to_string(), default_seq("uy"));
+        normal.insert(Action::PasteURL.to_string(), default_seq("up"));
+
+        normal.insert(Action::SearchNext.to_string(), default_seq("n"));
+        normal.insert(Action::SearchPrev.to_string(), default_seq("N"));
+        normal.insert(Action::SearchMode.to_string(), default_seq("/"));
+
+        normal.insert(Action::TabNew(String::new()).to_string(), default_seq("t"));
+        normal.insert(Action::TabClose.to_string(), default_seq("d"));
+        normal.insert(Action::TabNext.to_string(), default_seq("gt"));
+        normal.insert(Action::TabPrev.to_string(), default_seq("gT"));
+        normal.insert(Action::TabMoveLeft.to_string(), default_seq("gK"));
+        normal.insert(Action::TabMoveRight.to_string(), default_seq("gJ"));
+        normal.insert(Action::TabDuplicate.to_string(), default_seq("gC"));
+        normal.insert(Action::TabUndo.to_string(), default_seq("U"));
+        normal.insert(Action::ShowBuffers.to_string(), default_seq("b"));
         normal.insert(
-            Action::ScrollBottom.to_string(),
-            KeySequence::from_str("gb"),
+            Action::WindowNew(String::new()).to_string(),
+            default_seq("W"),
         );
+        normal.insert(Action::WindowClose.to_string(), default_seq("D"));
+        normal.insert(Action::MarkSet(String::new()).to_string(), default_seq("m"));
         normal.insert(
-            Action::ScrollHalfDown.to_string(),
-            KeySequence::from_str("C-d"),
+            Action::MarkJump(String::new()).to_string(),
+            default_seq("'"),
         );
         normal.insert(
-            Action::ScrollHalfUp.to_string(),
-            KeySequence::from_str("C-u"),
+            Action::BookmarkAdd(String::new()).to_string(),
+            default_seq("A"),
         );
-        normal.insert(Action::GoBack.to_string(), KeySequence::from_str("h"));
-        normal.insert(Action::GoForward.to_string(), KeySequence::from_str("l"));
-        normal.insert(Action::InsertMode.to_string(), KeySequence::from_str("i"));
-        normal.insert(Action::CmdMode.to_string(), KeySequence::from_str(":"));
-        normal.insert(Action::HintMode.to_string(), KeySequence::from_str("f"));
-
-        normal.insert(Action::ShowHelp.to_string(), KeySequence::from_str("?"));
-        normal.insert(Action::ShowURL.to_string(), KeySequence::from_str("go"));
         normal.insert(
-            Action::HardRefreshURL.to_string(),
-            KeySequence::from_str("R"),
+            Action::QuickmarkSet(String::new()).to_string(),
+            default_seq("M"),
         );
         normal.insert(
-            Action::SoftRefreshURL.to_string(),
-            KeySequence::from_str("r"),
+            Action::QuickmarkOpen(String::new()).to_string(),
+            default_seq("go"),
         );
-        normal.insert(Action::CopyURL.to_string(), KeySequence::from_str("uy"));
-        normal.insert(Action::PasteURL.to_string(), KeySequence::from_str("up"));
-
-        normal.insert(Action::SearchNext.to_string(), KeySequence::from_str("n"));
-        normal.insert(Action::SearchPrev.to_string(), KeySequence::from_str("N"));
-        normal.insert(Action::SearchMode.to_string(), KeySequence::from_str("/"));
         bindings.insert(KeyMode::Normal, normal);
 
-        Self { bindings }
+        Self {
+            bindings,
+            dirty: false,
+        }
     }
     pub fn new(config: Option<&KeybindingConfig>) -> Result<Self, String> {
         let mut manager = KeybindingManager::with_defaults();
 
         if let Some(cfg) = config {
             for (mode_str, map) in &cfg.bindings {
-                let mode = KeyMode::from_str(&mode_str.to_lowercase())
+                let mode = KeyMode::from_str(mode_str)
                     .map_err(|_| format!("Unknown mode: {}", mode_str))?;
 
-                let mode_map = manager.bindings.entry(mode).or_default();
-
                 for (seq_str, cmd) in map {
-                    let seq = KeySequence::from_str(seq_str);
-
-                    for other_seq in mode_map.values() {
-                        if seq.is_prefix_of(other_seq) || other_seq.is_prefix_of(&seq) {
-                            return Err(format!(
-                                "Invalid binding: sequence {:?} for command '{}' conflicts with {:?}",
-                                seq, cmd, other_seq
-                            ));
-                        }
-                    }
-
-                    mode_map.insert(cmd.clone(), seq);
+                    manager.bind(mode, seq_str, cmd)?;
                 }
             }
         }
+        manager.dirty = false;
 
         Ok(manager)
     }
+
+    /// Binds `seq_str` to `cmd` in `mode`, replacing the previous sequence of
+    /// `cmd` and any other command bound to exactly the same sequence.
+    ///
+    /// `cmd` is an action name, optionally followed by `:` and its argument,
+    /// as in `hint-yank-url:images`.
+    pub fn bind(&mut self, mode: KeyMode, seq_str: &str, cmd: &str) -> Result<(), String> {
+        let (name, arg) = cmd.split_once(':').unwrap_or((cmd, ""));
+        let action = name
+            .parse::<Action>()
+            .map_err(|_| format!("Unknown action '{}' bound to {:?}", cmd, seq_str))?;
+        if name != cmd {
+            action.with_args(&[arg.to_string()])?;
+        }
+        let seq: KeySequence = seq_str.parse()?;
+
+        let mode_map = self.bindings.entry(mode).or_default();
+        for (other_cmd, other_seq) in mode_map.iter() {
+            if other_cmd == cmd {
+                continue;
+            }
+            if seq.is_prefix_of(other_seq) || other_seq.is_prefix_of(&seq) {
+                return Err(format!(
+                    "Invalid binding: sequence {} for command '{}' conflicts with {}",
+                    seq, cmd, other_seq
+                ));
+            }
+        }
+
+        mode_map.retain(|_, other_seq| *other_seq != seq);
+        mode_map.insert(cmd.to_string(), seq);
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn unbind(&mut self, mode: KeyMode, seq_str: &str) -> Result<(), String> {
+        let seq: KeySequence = seq_str.parse()?;
+        let mode_map = self.bindings.entry(mode).or_default();
+        let before = mode_map.len();
+        mode_map.retain(|_, other_seq| *other_seq != seq);
+        if mode_map.len() == before {
+            return Err(format!("No binding for {} in {} mode", seq_str, mode));
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Whether bindings changed at runtime and differ from the ones baked
+    /// into the initialization script.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
 }
 
 impl KeybindingManager {
@@ -146,27 +400,68 @@ impl KeybindingManager {
             .get(&mode)
             .map(|map| {
                 map.iter()
-                    .map(|(key, seq)| (key.clone(), seq.0.join("")))
+                    .map(|(key, seq)| (key.clone(), seq.to_string()))
                     .collect()
             })
             .unwrap_or_default()
     }
 
+    /// Exports the bindings as `{ mode: [[keys, command], ...] }` for
+    /// `loadKeyTries` in the injected script.
+    pub fn export_tries(&self) -> HashMap<String, Vec<(Vec<String>, &str)>> {
+        self.bindings
+            .iter()
+            .filter(|(mode, _)| **mode != KeyMode::Cmd)
+            .map(|(mode, map)| {
+                let bindings = map
+                    .iter()
+                    .map(|(cmd, seq)| (seq.0.iter().map(Key::to_string).collect(), cmd.as_str()))
+                    .collect();
+                (mode.to_string(), bindings)
+            })
+            .collect()
+    }
+
     pub fn export_full_js(&self) -> String {
         let mut js = String::new();
 
         js.push_str(
-            r#"window.appState = {
+            r#"const appState = {
   mode: "Normal",
   commandBuffer: "",
   searchBuffer: "",
+  count: "",
 };
+exposeEntryPoint("setMode", (mode) => {
+  appState.mode = mode;
+});
 "#,
         );
 
+        let key_names: HashMap<&str, &str> = NAMED_KEYS.iter().map(|(n, d)| (*d, *n)).collect();
+        js.push_str(&format!(
+            "const keyNames = {};\n",
+            serde_json::to_string(&key_names).unwrap()
+        ));
+        js.push_str(&format!(
+            "const keyArgumentCommands = {};\n",
+            serde_json::to_string(&key_argument_commands()).unwrap()
+        ));
         js.push_str(
             r#"
-const sendAction = (cmd) => window.ipc.postMessage(cmd);
+// Mirrors `Key`'s Display in key.rs so event keys match configured sequences.
+const keyNotation = (e) => {
+  if (["Shift", "Control", "Alt", "Meta"].includes(e.key)) return null;
+  const printable = [...e.key].length === 1;
+  const named = keyNames[e.key] || (printable ? null : e.key);
+  let mods = "";
+  if (e.ctrlKey) mods += "C-";
+  if (e.altKey) mods += "A-";
+  if (e.metaKey) mods += "M-";
+  if (e.shiftKey && !printable) mods += "S-";
+  if (!mods && !named) return e.key;
+  return "<" + mods + (named || e.key) + ">";
+};
 "#,
         );
 
@@ -216,120 +511,149 @@ class KeyTrie {
 "#,
         );
 
-        js.push_str("window.keyTries = {};\n");
-        for (mode, map) in &self.bindings {
-            let mode_str = mode.to_string();
-
-            if mode_str != "Cmd" {
-                js.push_str(&format!(
-                    "window.keyTries['{}'] = new KeyTrie();\n",
-                    mode_str
-                ));
-                for (cmd, seq) in map {
-                    let keys: Vec<String> = seq.0.iter().map(|s| format!("\"{}\"", s)).collect();
-                    js.push_str(&format!(
-                        "window.keyTries['{}'].insert([{}], \"{}\");\n",
-                        mode_str,
-                        keys.join(", "),
-                        cmd
-                    ));
-                }
-            }
-        }
+        js.push_str(
+            r#"
+const keyTries = {};
+const loadKeyTries = (tries) => {
+  for (const mode of Object.keys(keyTries)) delete keyTries[mode];
+  for (const [mode, bindings] of Object.entries(tries)) {
+    keyTries[mode] = new KeyTrie();
+    for (const [sequence, command] of bindings) keyTries[mode].insert(sequence, command);
+  }
+};
+exposeEntryPoint("setKeyTries", loadKeyTries);
+"#,
+        );
+        js.push_str(&script::call("loadKeyTries", (self.export_tries(),)));
 
         js.push_str(
             r#"
+let pendingCommand = null;
 document.addEventListener("keydown", (e) => {
+  if (!e.isTrusted) return;
   e.stopPropagation();
+  flushScroll();
+
+  if (e.key === "Escape" && appState.count) {
+    appState.count = "";
+    updateStatus(appState.mode);
+  }
 
-  if (e.key === "Escape" && window.appState.mode !== "Normal") {
-    window.clearHints();
-    window.appState.mode = "Normal";
+  if (e.key === "Escape" && appState.mode !== "Normal") {
+    clearHints();
+    hideCompletions();
+    appState.mode = "Normal";
     sendAction("normal-mode");
-    window.updateStatus(window.appState.mode);
-    if (window.keyTries[window.appState.mode])
-      window.keyTries[window.appState.mode].reset();
+    updateStatus(appState.mode);
+    if (keyTries[appState.mode])
+      keyTries[appState.mode].reset();
     e.preventDefault();
     return;
   }
 
-  let key = e.key;
-  if (e.ctrlKey) key = "C-" + key;
-
-  if (window.appState.mode === "Hint") {
-    if (key === "Escape") {
-      window.clearHints();
-      window.appState.mode = "Normal";
-      sendAction("normal-mode");
+  const key = e.key;
 
-      window.updateStatus(window.appState.mode);
+  if (appState.mode === "Hint") {
+    hintKeydown(e);
+    e.preventDefault();
+    return;
+  } else if (appState.mode === "Cmd") {
+    if (key === "Tab") {
+      if (cmdline.completion) {
+        cycleCompletion(e.shiftKey ? -1 : 1);
+      } else {
+        sendAction("complete:" + (appState.commandBuffer || ""));
+      }
       e.preventDefault();
+      return;
     }
-    window.hintState.buffer += e.key.toUpperCase();
-        const match = window.hintState.hints.find(
-            h => h.label === window.hintState.buffer
-        );
-
-        if (match) {
-
-            window.appState.mode = "Normal";
-            sendAction("normal-mode");
-            match.link.click();
-            window.clearHints();
-    }
-    return;
-  } else if (window.appState.mode === "Cmd") {
-    if (key === "Enter") {
-      sendAction("command:" + window.appState.commandBuffer);
-      window.appState.commandBuffer = "";
-      window.appState.mode = "Normal";
+    hideCompletions();
+    if (key === "ArrowUp" || key === "ArrowDown") {
+      recallCommand(key === "ArrowUp" ? -1 : 1);
+      e.preventDefault();
+    } else if (key === "Enter") {
+      sendAction("command:" + appState.commandBuffer);
+      appState.commandBuffer = "";
+      appState.mode = "Normal";
       sendAction("normal-mode");
 
-      window.updateStatus(window.appState.mode);
+      updateStatus(appState.mode);
       e.preventDefault();
     } else if (key === "Backspace") {
-        if (window.appState.commandBuffer && window.appState.commandBuffer.length > 0) {
-            window.appState.commandBuffer = window.appState.commandBuffer.slice(0, -1);
-            window.updateStatus(":" + window.appState.commandBuffer);
+        if (appState.commandBuffer && appState.commandBuffer.length > 0) {
+            appState.commandBuffer = appState.commandBuffer.slice(0, -1);
+            updateStatus(":" + appState.commandBuffer);
         }
         e.preventDefault();
      }else if (key.length === 1 && !e.ctrlKey && !e.metaKey) {
-      window.appState.commandBuffer = window.appState.commandBuffer || "";
-      window.appState.commandBuffer += key;
+      appState.commandBuffer = appState.commandBuffer || "";
+      appState.commandBuffer += key;
 
-      window.updateStatus(":" + window.appState.commandBuffer);
+      updateStatus(":" + appState.commandBuffer);
       e.preventDefault();
     }
     return;
-  } else if (window.appState.mode === "Search") {
+  } else if (appState.mode === "Search") {
     if (key === "Enter") {
-      window.appState.mode = "Normal";
-      sendAction("search:" + window.appState.searchBuffer);
+      appState.mode = "Normal";
+      sendAction("search:" + appState.searchBuffer);
       sendAction("normal-mode");
-      window.updateStatus(window.appState.mode);
+      updateStatus(appState.mode);
       e.preventDefault();
     } else if (key === "Backspace") {
-        if (window.appState.searchBuffer && window.appState.searchBuffer.length > 0) {
-            window.appState.searchBuffer = window.appState.searchBuffer.slice(0, -1);
-            window.updateStatus("/" + window.appState.searchBuffer);
+        if (appState.searchBuffer && appState.searchBuffer.length > 0) {
+            appState.searchBuffer = appState.searchBuffer.slice(0, -1);
+            updateStatus("/" + appState.searchBuffer);
         }
         e.preventDefault();
      }else if (key.length === 1 && !e.ctrlKey && !e.metaKey) {
-      window.appState.searchBuffer = window.appState.searchBuffer || "";
-      window.appState.searchBuffer += key;
-      window.updateStatus("/" + window.appState.searchBuffer);
+      appState.searchBuffer = appState.searchBuffer || "";
+      appState.searchBuffer += key;
+      updateStatus("/" + appState.searchBuffer);
       e.preventDefault();
     }
     return;
   }
   else {
 
-  const trie = window.keyTries[window.appState.mode];
+  const trie = keyTries[appState.mode];
   if (!trie) return;
 
-  const cmd = trie.processKey(key);
+  const notation = keyNotation(e);
+  if (notation === null) return;
+
+  if (pendingCommand !== null) {
+    // Any key that is not a character cancels the command.
+    if ([...notation].length === 1) sendAction(pendingCommand + ":" + notation);
+    pendingCommand = null;
+    updateStatus(appState.mode);
+    e.preventDefault();
+    return;
+  }
+
+  const isCountDigit = /^[0-9]$/.test(notation) && (notation !== "0" || appState.count);
+  if (appState.mode === "Normal" && isCountDigit
+      && trie.currentNode === trie.root && !trie.root.children.has(notation)) {
+    appState.count += notation;
+    updateStatus(appState.mode + "  " + appState.count);
+    e.preventDefault();
+    return;
+  }
+
+  const cmd = trie.processKey(notation);
+  if (cmd && keyArgumentCommands.includes(cmd)) {
+    pendingCommand = appState.count + cmd;
+    appState.count = "";
+    updateStatus(appState.mode + "  " + cmd);
+    e.preventDefault();
+    return;
+  }
   if (cmd) {
-    sendAction(cmd);
+    sendAction(appState.count + cmd);
+    if (appState.count) {
+      appState.count = "";
+      updateStatus(appState.mode);
+    }
     e.preventDefault();
 
   const modeCommands = ["normal-mode", "insert-mode", "cmd-mode", "search-mode", "hint-mode"];
@@ -339,37 +663,41 @@ document.addEventListener("keydown", (e) => {
     switch (cmd) {
         case "normal-mode":
             displayMode = "Normal";
-            window.updateStatus(displayMode);
+            updateStatus(displayMode);
             break;
         case "insert-mode":
             displayMode = "Insert";
-            window.updateStatus(displayMode);
+            updateStatus(displayMode);
             break;
         case "cmd-mode":
             displayMode = "Cmd";
-            window.appState.commandBuffer = "";
-            window.updateStatus(":");
+            appState.commandBuffer = "";
+            updateStatus(":");
             break;
         case "search-mode":
             displayMode = "Search";
 
-            window.appState.searchBuffer = "";
-            window.updateStatus("/");
+            appState.searchBuffer = "";
+            updateStatus("/");
             break;
         case "hint-mode":
+            // Peyvand shows the hints once it has switched modes.
             displayMode = "Hint";
-            window.updateStatus(displayMode);
-            window.showHints();
+            updateStatus(displayMode);
             break;
     }
 
-    window.appState.mode = displayMode;
+    appState.mode = displayMode;
 
-    const newTrie = window.keyTries[displayMode];
+    const newTrie = keyTries[displayMode];
     if (newTrie) newTrie.reset();
 }
     } else if (cmd === null) {
     trie.reset(); // invalid sequence
+    if (appState.count) {
+      appState.count = "";
+      updateStatus(appState.mode);
+    }
    }
   }
 });
@@ -378,10 +706,8 @@ document.addEventListener("keydown", (e) => {
 
         js.push_str(
 r#"
-window.overlays = window.overlays || {};
-
-window.showHelp = function(bindings) {
-    if (window.overlays.help) window.overlays.help.remove();
+exposeEntryPoint("showHelp", (bindings) => {
+    if (overlays.help) overlays.help.remove();
 
     const overlay = document.createElement("div");
     overlay.id = "help-overlay";
@@ -398,21 +724,30 @@ window.showHelp = function(bindings) {
     overlay.style.padding = "1em";
     overlay.style.borderTop = "2px solid #ccc";
 
-    let content = "<h3>Key Bindings</h3><ul>";
+    const heading = document.createElement("h3");
+    heading.textContent = "Key Bindings";
+    overlay.appendChild(heading);
+    const list = document.createElement("ul");
     for (const key in bindings) {
-        content += `<li><b>${key}</b> — ${bindings[key]}</li>`;
+        const item = document.createElement("li");
+        const name = document.createElement("b");
+        name.textContent = key;
+        item.append(name, ` — ${bindings[key]}`);
+        list.appendChild(item);
     }
-    content += "</ul><p>Press ESC to close</p>";
-    overlay.innerHTML = content;
+    overlay.appendChild(list);
+    const footer = document.createElement("p");
+    footer.textContent = "Press ESC to close";
+    overlay.appendChild(footer);
 
     document.body.appendChild(overlay);
-    window.overlays.help = overlay;
+    overlays.help = overlay;
 
-    function remove() { overlay.remove(); window.overlays.help = null; document.removeEventListener("keydown", escHandler); }
+    function remove() { overlay.remove(); overlays.help = null; document.removeEventListener("keydown", escHandler); }
     function escHandler(e) { if (e.key === "Escape") remove(); }
     overlay.addEventListener("click", remove);
     document.addEventListener("keydown", escHandler);
-};
+});
 "#
 );
 
@@ -420,4 +755,101 @@ window.showHelp = function(bindings) {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(s: &str) -> String {
+        s.parse::<KeySequence>().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_plain_keys() {
+        let seq: KeySequence = "gt".parse().unwrap();
+        assert_eq!(seq.0.len(), 2);
+        assert_eq!(round_trip("gt"), "gt");
+        assert_eq!(round_trip("N"), "N");
+    }
+
+    #[test]
+    fn test_modifier_notation() {
+        let seq: KeySequence = "<C-d>".parse().unwrap();
+        assert_eq!(seq.0.len(), 1);
+        assert!(seq.0[0].ctrl);
+        assert_eq!(seq.0[0].name, "d");
+
+        assert_eq!(round_trip("<C-d>"), "<C-d>");
+        assert_eq!(round_trip("<c-d>"), "<C-d>");
+        assert_eq!(round_trip("<A-x>"), "<A-x>");
+        assert_eq!(round_trip("<M-C-x>"), "<C-M-x>");
+        assert_eq!(round_trip("<S-Tab>"), "<S-Tab>");
+        assert_eq!(round_trip("<C-->"), "<C-->");
+    }
+
+    #[test]
+    fn test_named_keys() {
+        assert_eq!(round_trip("<Esc>"), "<Esc>");
+        assert_eq!(round_trip("<escape>"), "<Esc>");
+        assert_eq!(round_trip("<Space>"), "<Space>");
+        assert_eq!(round_trip(" "), "<Space>");
+        assert_eq!(round_trip("<F5>"), "<F5>");
+        assert_eq!(round_trip("<Enter>"), "<CR>");
+        assert_eq!(round_trip("<lt>"), "<lt>");
+        assert_eq!(round_trip("<"), "<lt>");
+        assert_eq!(round_trip("<>"), "<lt>>");
+        assert_eq!(round_trip("g<Esc>x"), "g<Esc>x");
+    }
+
+    #[test]
+    fn test_shift_is_folded_into_printable_keys() {
+        assert_eq!(round_trip("<S-a>"), "A");
+        assert_eq!(round_trip("<C-S-a>"), "<C-A>");
+        assert_eq!(round_trip("<S-Space>"), "<Space>");
+    }
+
+    #[test]
+    fn test_invalid_notation() {
+        assert!("<Foo>".parse::<KeySequence>().is_err());
+        assert!("<X-a>".parse::<KeySequence>().is_err());
+        assert!("<F25>".parse::<KeySequence>().is_err());
+        assert!("".parse::<KeySequence>().is_err());
+    }
+
+    #[test]
+    fn test_default_bindings_match_js_notation() {
+        let manager = KeybindingManager::with_defaults();
+        let help = manager.get_help_map(KeyMode::Normal);
+        assert_eq!(help["scroll-half-down"], "<C-d>");
+        assert_eq!(help["scroll-down"], "j");
+    }
+
+    #[test]
+    fn test_bind_conflicts() {
+        let mut manager = KeybindingManager::with_defaults();
+        assert!(manager.bind(KeyMode::Normal, "g", "scroll-down").is_err());
+        assert!(manager.bind(KeyMode::Normal, "j", "scroll-up").is_ok());
+        let help = manager.get_help_map(KeyMode::Normal);
+        assert_eq!(help["scroll-up"], "j");
+        assert!(!help.contains_key("scroll-down"));
+        assert!(manager
+            .bind(KeyMode::Normal, "x", "no-such-action")
+            .is_err());
+    }
+
+    #[test]
+    fn test_bind_with_argument() {
+        let mut manager = KeybindingManager::with_defaults();
+        assert!(manager
+            .bind(KeyMode::Normal, ";i", "hint-yank-url:images")
+            .is_ok());
+        assert_eq!(
+            manager.get_help_map(KeyMode::Normal)["hint-yank-url:images"],
+            ";i"
+        );
+        assert!(manager
+            .bind(KeyMode::Normal, ";I", "hint-yank-url:videos")
+            .is_err());
+    }
+}
+
 /* key.rs ends here */
@@ -23,18 +23,32 @@ mod action;
 mod agent;
 mod app;
 mod args;
+mod bookmark;
+mod cmdline;
+mod command;
+mod config;
 mod cookie;
+mod download;
+mod event;
+mod fuzzy;
 mod hint;
 mod history;
+mod ipc;
 mod key;
+mod mark;
+mod overlay;
+mod script;
 mod search;
+mod session;
 mod signal;
 mod state;
 mod statusbar;
+mod tab;
 mod url;
+mod window;
 
 use app::Application;
-use signal::setup_signal_handlers;
+use config::Config;
 use spdlog::{debug, info, Level, LevelFilter, Logger};
 use std::sync::Arc;
 
@@ -57,10 +71,10 @@ fn main() -> anyhow::Result<()> {
     info!("Started: Peyvand, PID: {}", pid);
     debug!("{:#?}", args);
 
-    setup_signal_handlers()?;
-    debug!("Finished: setup signals");
+    let config = Config::load(&args)?;
+    debug!("{:#?}", config);
 
-    let mut app = Application::new(args);
+    let mut app = Application::new(args, config);
     app.start()
 }
 
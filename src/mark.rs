@@ -0,0 +1,149 @@
+/* mark.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::config;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+/// The `''` mark, set automatically before large jumps.
+pub const PREVIOUS_MARK: char = '\'';
+
+/// A position on a page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mark {
+    pub url: String,
+    pub scroll: (f64, f64),
+}
+
+/// Vim-style marks: lowercase ones (and `''`) belong to the page they were
+/// set on, uppercase ones are global and kept in `marks.json`.
+pub struct Marks {
+    file: Option<PathBuf>,
+    global: BTreeMap<char, Mark>,
+    local: HashMap<String, HashMap<char, (f64, f64)>>,
+}
+
+impl Marks {
+    pub fn load(file: Option<PathBuf>) -> Self {
+        let global = config::load_json(file.as_deref(), "marks");
+        Self {
+            file,
+            global,
+            local: HashMap::new(),
+        }
+    }
+
+    /// Parses the name of a mark, which is a single letter or `'`.
+    pub fn parse_name(name: &str) -> Result<char, String> {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphabetic() || c == PREVIOUS_MARK => Ok(c),
+            _ => Err(format!("Invalid mark: {name}")),
+        }
+    }
+
+    pub fn set(&mut self, name: char, url: &str, scroll: (f64, f64)) {
+        if name.is_ascii_uppercase() {
+            let mark = Mark {
+                url: url.to_string(),
+                scroll,
+            };
+            self.global.insert(name, mark);
+            self.save();
+        } else {
+            self.local
+                .entry(page(url).to_string())
+                .or_default()
+                .insert(name, scroll);
+        }
+    }
+
+    /// Looks up a mark as seen from the page at `url`.
+    pub fn get(&self, name: char, url: &str) -> Option<Mark> {
+        if name.is_ascii_uppercase() {
+            return self.global.get(&name).cloned();
+        }
+        let scroll = *self.local.get(page(url))?.get(&name)?;
+        Some(Mark {
+            url: url.to_string(),
+            scroll,
+        })
+    }
+
+    fn save(&self) {
+        config::save_json(self.file.as_deref(), &self.global, "marks");
+    }
+}
+
+/// The page a URL points into; fragments only move within it.
+pub fn page(url: &str) -> &str {
+    url.split_once('#').map_or(url, |(page, _)| page)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_marks_belong_to_the_page() {
+        let mut marks = Marks::load(None);
+        marks.set('a', "https://a.org/doc#intro", (0.0, 500.0));
+
+        let mark = marks.get('a', "https://a.org/doc#usage").unwrap();
+        assert_eq!(mark.scroll, (0.0, 500.0));
+        assert_eq!(mark.url, "https://a.org/doc#usage");
+        assert_eq!(marks.get('a', "https://b.org/"), None);
+        assert_eq!(marks.get('b', "https://a.org/doc"), None);
+
+        marks.set(PREVIOUS_MARK, "https://b.org/", (0.0, 10.0));
+        assert!(marks.get(PREVIOUS_MARK, "https://b.org/").is_some());
+        assert!(marks.get(PREVIOUS_MARK, "https://a.org/doc").is_none());
+    }
+
+    #[test]
+    fn test_global_marks_are_persisted() {
+        let path = std::env::temp_dir().join(format!("peyvand-marks-{}.json", std::process::id()));
+        let mut marks = Marks::load(Some(path.clone()));
+        marks.set('A', "https://a.org/doc", (0.0, 500.0));
+
+        let expected = Mark {
+            url: "https://a.org/doc".to_string(),
+            scroll: (0.0, 500.0),
+        };
+        assert_eq!(marks.get('A', "https://b.org/"), Some(expected.clone()));
+        assert_eq!(Marks::load(Some(path.clone())).get('A', ""), Some(expected));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_name() {
+        assert_eq!(Marks::parse_name("a"), Ok('a'));
+        assert_eq!(Marks::parse_name("Z"), Ok('Z'));
+        assert_eq!(Marks::parse_name("'"), Ok(PREVIOUS_MARK));
+        assert!(Marks::parse_name("").is_err());
+        assert!(Marks::parse_name("ab").is_err());
+        assert!(Marks::parse_name("1").is_err());
+        assert!(Marks::parse_name("é").is_err());
+    }
+}
+
+/* mark.rs ends here */
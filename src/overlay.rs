@@ -0,0 +1,158 @@
+/* overlay.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+const OVERLAY_JS: &str = r#"
+const overlays = {};
+
+exposeEntryPoint("showList", (title, items) => {
+    if (overlays.list) overlays.list.remove();
+
+    const overlay = document.createElement("div");
+    Object.assign(overlay.style, {
+        position: "fixed",
+        bottom: "24px",
+        left: "0",
+        width: "100%",
+        maxHeight: "40%",
+        background: "rgba(0,0,0,0.85)",
+        color: '#eee',
+        zIndex: 9999,
+        fontFamily: "monospace",
+        overflowY: "auto",
+        padding: "1em",
+        borderTop: '2px solid #ccc',
+        boxSizing: "border-box",
+    });
+
+    const heading = document.createElement("h3");
+    heading.textContent = title;
+    overlay.appendChild(heading);
+
+    const list = document.createElement("ul");
+    for (const item of items) {
+        const li = document.createElement("li");
+        li.textContent = item;
+        list.appendChild(li);
+    }
+    overlay.appendChild(list);
+
+    const footer = document.createElement("p");
+    footer.textContent = "Press ESC to close";
+    overlay.appendChild(footer);
+
+    const host = mountOverlay(overlay);
+    overlays.list = host;
+
+    function remove() { host.remove(); overlays.list = null; document.removeEventListener("keydown", escHandler); }
+    function escHandler(e) { if (e.key === "Escape") remove(); }
+    overlay.addEventListener("click", remove);
+    document.addEventListener("keydown", escHandler);
+});
+
+// `d` runs the command in `deletions` for the selected row, if any.
+exposeEntryPoint("showPicker", (title, items, selected, commands, deletions = []) => {
+    if (overlays.picker) overlays.picker.close();
+
+    const overlay = document.createElement("div");
+    Object.assign(overlay.style, {
+        position: "fixed",
+        bottom: "24px",
+        left: "0",
+        width: "100%",
+        maxHeight: "40%",
+        background: "rgba(0,0,0,0.85)",
+        color: '#eee',
+        zIndex: 9999,
+        fontFamily: "monospace",
+        overflowY: "auto",
+        padding: "1em",
+        borderTop: '2px solid #ccc',
+        boxSizing: "border-box",
+    });
+
+    const heading = document.createElement("h3");
+    heading.textContent = title;
+    overlay.appendChild(heading);
+
+    const rows = items.map((item) => {
+        const row = document.createElement("div");
+        row.textContent = item;
+        overlay.appendChild(row);
+        return row;
+    });
+
+    const footer = document.createElement("p");
+    footer.textContent = "j/k to move, "
+        + (commands.length ? "Enter to select, " : "")
+        + (deletions.length ? "d to delete, " : "")
+        + "ESC to close";
+    overlay.appendChild(footer);
+    const host = mountOverlay(overlay);
+
+    let index = Math.max(0, Math.min(items.length - 1, selected));
+    const render = () => {
+        rows.forEach((row, i) => {
+            row.style.backgroundColor = i === index ? '#444' : "transparent";
+        });
+        if (rows[index]) rows[index].scrollIntoView({ block: "nearest" });
+    };
+    const close = () => {
+        host.remove();
+        window.removeEventListener("keydown", onKey, true);
+        overlays.picker = null;
+    };
+    // Captured on window so the picker sees keys before the normal-mode handler.
+    const onKey = (e) => {
+        if (!e.isTrusted) return;
+        e.preventDefault();
+        e.stopImmediatePropagation();
+        if (e.key === "j" || e.key === "ArrowDown") {
+            index = Math.min(items.length - 1, index + 1);
+        } else if (e.key === "k" || e.key === "ArrowUp") {
+            index = Math.max(0, index - 1);
+        } else if (/^[1-9]$/.test(e.key) && Number(e.key) <= items.length) {
+            index = Number(e.key) - 1;
+        } else if (e.key === "Enter" || (e.key === "d" && deletions.length)) {
+            const command = (e.key === "Enter" ? commands : deletions)[index];
+            close();
+            if (command) sendAction("command:" + command);
+            return;
+        } else if (e.key === "Escape" || e.key === "q") {
+            close();
+            return;
+        }
+        render();
+    };
+    window.addEventListener("keydown", onKey, true);
+    overlays.picker = { close };
+    render();
+});
+"#;
+
+pub struct Overlay {}
+
+impl Overlay {
+    pub fn get_js() -> &'static str {
+        OVERLAY_JS
+    }
+}
+
+/* overlay.rs ends here */
@@ -0,0 +1,187 @@
+/* script.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use serde::Serialize;
+use serde_json::Value;
+use spdlog::error;
+use wry::WebView;
+
+const SCRIPT_JS: &str = r#"
+exposeEntryPoint("scrollToBottom", () => {
+  const root = document.scrollingElement || document.documentElement;
+  window.scrollTo(0, root.scrollHeight);
+});
+"#;
+
+pub struct Script {}
+
+impl Script {
+    pub fn get_js() -> &'static str {
+        SCRIPT_JS
+    }
+}
+
+/// Builds a call to the injected function `function`, with every argument
+/// encoded as a JSON literal so that no input is ever parsed as code.
+///
+/// `args` is a tuple: `()` for no arguments and `(arg,)` for a single one.
+pub fn call<A: Serialize>(function: &str, args: A) -> String {
+    debug_assert!(is_function_path(function), "invalid function {function}");
+    let args = values(args)
+        .iter()
+        .map(literal)
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("{function}({args});")
+}
+
+/// Builds a call to an entry point defined with `exposeEntryPoint`, which
+/// ignores calls that do not pass `token` first.
+pub fn call_locked<A: Serialize>(token: &str, function: &str, args: A) -> String {
+    let mut args = values(args);
+    args.insert(0, Value::from(token));
+    call(function, args)
+}
+
+fn values<A: Serialize>(args: A) -> Vec<Value> {
+    match serde_json::to_value(args).expect("script arguments are valid JSON") {
+        Value::Null => Vec::new(),
+        Value::Array(values) => values,
+        value => vec![value],
+    }
+}
+
+/// Encodes a value as a JavaScript literal. JSON is valid JavaScript except
+/// for raw line and paragraph separators, which older engines reject inside
+/// string literals.
+fn literal(value: &Value) -> String {
+    value
+        .to_string()
+        .replace('\u{2028}', "\\u2028")
+        .replace('\u{2029}', "\\u2029")
+}
+
+/// Whether `function` is a dotted path such as `window.showList`.
+fn is_function_path(function: &str) -> bool {
+    function.split('.').all(|part| {
+        let mut chars = part.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+    })
+}
+
+/// Calls injected functions in a webview.
+pub trait Eval {
+    fn call<A: Serialize>(&self, function: &str, args: A);
+    fn call_locked<A: Serialize>(&self, token: &str, function: &str, args: A);
+}
+
+impl Eval for WebView {
+    fn call<A: Serialize>(&self, function: &str, args: A) {
+        if let Err(e) = self.evaluate_script(&call(function, args)) {
+            error!("Failed to call {}: {}", function, e);
+        }
+    }
+
+    fn call_locked<A: Serialize>(&self, token: &str, function: &str, args: A) {
+        if let Err(e) = self.evaluate_script(&call_locked(token, function, args)) {
+            error!("Failed to call {}: {}", function, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the argument list of a generated call back into JSON values.
+    fn arguments(script: &str, function: &str) -> Vec<Value> {
+        let args = script
+            .strip_prefix(function)
+            .and_then(|s| s.strip_prefix('('))
+            .and_then(|s| s.strip_suffix(");"))
+            .unwrap();
+        serde_json::from_str(&format!("[{args}]")).unwrap()
+    }
+
+    #[test]
+    fn test_call_arguments() {
+        assert_eq!(call("window.searchNext", ()), "window.searchNext();");
+        assert_eq!(call("history.go", (-2,)), "history.go(-2);");
+        assert_eq!(
+            call("window.showMessage", ("done", false)),
+            r#"window.showMessage("done", false);"#
+        );
+        assert_eq!(
+            call("window.showList", ("History", ["a", "b"])),
+            r#"window.showList("History", ["a","b"]);"#
+        );
+    }
+
+    #[test]
+    fn test_call_hostile_inputs() {
+        let hostile = [
+            r#"");alert(1);//"#,
+            r#"'); window.location.href = 'https://evil'; ('"#,
+            "back\\slash\\\"",
+            "`${alert(1)}`",
+            "line\nbreak\r\u{0}",
+            "</script><script>alert(1)</script>",
+            "separators\u{2028}and\u{2029}",
+            "javascript:alert(document.cookie)",
+        ];
+        for input in hostile {
+            let script = call("window.searchHighlight", (input,));
+            assert!(!script.contains('\u{2028}') && !script.contains('\u{2029}'));
+            assert!(!script.contains('\n'));
+            assert_eq!(
+                arguments(&script, "window.searchHighlight"),
+                vec![Value::from(input)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_call_locked_passes_the_token_first() {
+        assert_eq!(
+            call_locked("t0k", "window.setMode", ("Hint",)),
+            r#"window.setMode("t0k", "Hint");"#
+        );
+        assert_eq!(
+            call_locked("t0k", "window.showMessage", ("done", false)),
+            r#"window.showMessage("t0k", "done", false);"#
+        );
+        assert_eq!(call_locked("t0k", "window.f", ()), r#"window.f("t0k");"#);
+    }
+
+    #[test]
+    fn test_function_path() {
+        assert!(is_function_path("window.location.assign"));
+        assert!(is_function_path("$_a1"));
+        assert!(!is_function_path("alert(1);window.x"));
+        assert!(!is_function_path("window..x"));
+        assert!(!is_function_path("1x"));
+    }
+}
+
+/* script.rs ends here */
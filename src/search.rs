@@ -20,12 +20,12 @@
 */
 
 const SEARCH_JS: &str = r#"
-window.searchState = {
+const searchState = {
   matches: [],
   index: -1
 };
 
-window.clearHighlights = function () {
+const clearHighlights = () => {
   const marks = document.querySelectorAll('mark.pey-search-highlight');
   marks.forEach(mark => {
     const parent = mark.parentNode;
@@ -34,16 +34,16 @@ window.clearHighlights = function () {
     parent.removeChild(mark);
     parent.normalize();
   });
-  window.searchState.matches = [];
-  window.searchState.index = -1;
+  searchState.matches = [];
+  searchState.index = -1;
 };
 
 function escapeRegExp(s) {
   return s.replace(/[.*+?^${}()|[\]\\]/g, '\\$&');
 }
 
-window.searchHighlight = function (needle) {
-  window.clearHighlights();
+exposeEntryPoint("searchHighlight", (needle) => {
+  clearHighlights();
   if (!needle) return;
 
   const root = document.body || document.documentElement;
@@ -69,7 +69,7 @@ window.searchHighlight = function (needle) {
         }
         if (
           p.closest(
-            '.pey-search-highlight, #pey-status, #help-overlay, #url-bar-overlay'
+            '.pey-search-highlight, #pey-status, #help-overlay'
           )
         ) {
           return NodeFilter.FILTER_REJECT;
@@ -113,45 +113,46 @@ window.searchHighlight = function (needle) {
     node.parentNode.replaceChild(frag, node);
   });
 
-  window.searchState.matches = Array.from(
+  searchState.matches = Array.from(
     document.querySelectorAll('mark.pey-search-highlight')
   );
-  window.searchState.index = -1;
+  searchState.index = -1;
 
-   if (window.searchState.matches.length) {
-     window.searchState.matches[0].scrollIntoView({ behavior: 'instant', block: 'center' });
+   if (searchState.matches.length) {
+     searchState.matches[0].scrollIntoView({ behavior: 'instant', block: 'center' });
    }
-};
-window.searchNext = function () {
-  if (!window.searchState.matches.length) return;
+});
+exposeEntryPoint("searchNext", (count = 1) => {
+  if (!searchState.matches.length) return;
 
-  window.searchState.matches.forEach(m => {
+  searchState.matches.forEach(m => {
     m.style.background = "yellow";
   });
 
-  window.searchState.index =
-    (window.searchState.index + 1) % window.searchState.matches.length;
+  searchState.index =
+    (searchState.index + count) % searchState.matches.length;
 
-  const el = window.searchState.matches[window.searchState.index];
+  const el = searchState.matches[searchState.index];
   el.scrollIntoView({ behavior: "smooth", block: "center" });
   el.style.background = "red";
-};
+});
 
-window.searchPrev = function () {
-  if (!window.searchState.matches.length) return;
+exposeEntryPoint("searchPrev", (count = 1) => {
+  if (!searchState.matches.length) return;
 
-  window.searchState.matches.forEach(m => {
+  searchState.matches.forEach(m => {
     m.style.background = "yellow";
   });
 
-  window.searchState.index =
-    (window.searchState.index - 1 + window.searchState.matches.length) %
-    window.searchState.matches.length;
+  searchState.index =
+    (searchState.index - count % searchState.matches.length +
+      searchState.matches.length) %
+    searchState.matches.length;
 
-  const el = window.searchState.matches[window.searchState.index];
+  const el = searchState.matches[searchState.index];
   el.scrollIntoView({ behavior: "smooth", block: "center" });
   el.style.background = "red";
-};
+});
 
 "#;
 
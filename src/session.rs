@@ -0,0 +1,208 @@
+/* session.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::config;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Session used by `:session-save` and `:session-load` without a name.
+pub const DEFAULT_SESSION: &str = "default";
+
+/// Session written when peyvand exits and restored by a bare `--restore`.
+pub const LAST_SESSION: &str = "last";
+
+/// Session written periodically while peyvand runs, and removed again on a
+/// clean exit; if it is still there at startup, peyvand crashed.
+pub const AUTOSAVE_SESSION: &str = "autosave";
+
+const SESSION_JS: &str = r#"
+let scrollTimer = null;
+const reportScroll = () => {
+  scrollTimer = null;
+  sendAction('scroll:' + window.scrollX + ',' + window.scrollY);
+};
+// Reports a scroll still waiting for its debounce, so that commands acting
+// on the scroll offset see the current one.
+const flushScroll = () => {
+  if (scrollTimer !== null) {
+    clearTimeout(scrollTimer);
+    reportScroll();
+  }
+};
+window.addEventListener('scroll', () => {
+  clearTimeout(scrollTimer);
+  scrollTimer = setTimeout(reportScroll, 250);
+}, { passive: true });
+"#;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TabSession {
+    /// The back/forward list of the tab.
+    pub entries: Vec<String>,
+    /// The position of the current page in `entries`.
+    pub index: usize,
+    pub title: String,
+    /// Scroll offset of the current page.
+    pub scroll: (f64, f64),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowSession {
+    pub tabs: Vec<TabSession>,
+    pub current: usize,
+}
+
+/// The open windows and tabs, as written to `sessions/<name>.json` in the
+/// data directory.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub windows: Vec<WindowSession>,
+    pub active: usize,
+    pub mode: String,
+}
+
+impl Session {
+    pub fn get_js() -> &'static str {
+        SESSION_JS
+    }
+}
+
+/// Reads and writes named sessions in a directory.
+pub struct SessionStore {
+    dir: Option<PathBuf>,
+}
+
+impl SessionStore {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, String> {
+        validate_name(name)?;
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or("No data directory to keep sessions in")?;
+        Ok(dir.join(format!("{name}.json")))
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.path(name).is_ok_and(|path| path.exists())
+    }
+
+    pub fn load(&self, name: &str) -> anyhow::Result<Session> {
+        let path = self.path(name).map_err(anyhow::Error::msg)?;
+        let data =
+            std::fs::read_to_string(&path).with_context(|| format!("No session named {name}"))?;
+        let session: Session = serde_json::from_str(&data)
+            .with_context(|| format!("Corrupt session file {}", path.display()))?;
+        if session.windows.iter().all(|win| win.tabs.is_empty()) {
+            anyhow::bail!("Session {name} has no tabs");
+        }
+        Ok(session)
+    }
+
+    pub fn save(&self, name: &str, session: &Session) -> anyhow::Result<()> {
+        let path = self.path(name).map_err(anyhow::Error::msg)?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        config::write_atomic(&path, &serde_json::to_string(session)?)
+    }
+
+    pub fn remove(&self, name: &str) {
+        if let Ok(path) = self.path(name) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Session names become file names, so they are kept to a safe alphabet.
+fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid session name: {name}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        let tab = |urls: &[&str], index| TabSession {
+            entries: urls.iter().map(|url| url.to_string()).collect(),
+            index,
+            title: "Title".to_string(),
+            scroll: (0.0, 120.5),
+        };
+        Session {
+            windows: vec![
+                WindowSession {
+                    tabs: vec![tab(&["https://a.org/", "https://b.org/"], 1)],
+                    current: 0,
+                },
+                WindowSession {
+                    tabs: vec![tab(&["https://c.org/"], 0), tab(&["file:///tmp/x"], 0)],
+                    current: 1,
+                },
+            ],
+            active: 1,
+            mode: "Normal".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir().join(format!("peyvand-sessions-{}", std::process::id()));
+        let store = SessionStore::new(Some(dir.clone()));
+
+        assert!(!store.exists("work"));
+        assert!(store.load("work").is_err());
+        store.save("work", &session()).unwrap();
+        assert!(store.exists("work"));
+        assert_eq!(store.load("work").unwrap(), session());
+
+        store.remove("work");
+        assert!(!store.exists("work"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_session_names() {
+        assert!(validate_name("work").is_ok());
+        assert!(validate_name("client-2.old").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../config").is_err());
+        assert!(validate_name(".hidden").is_err());
+        assert!(validate_name("a/b").is_err());
+        assert!(SessionStore::new(None).save("work", &session()).is_err());
+    }
+}
+
+/* session.rs ends here */
@@ -19,17 +19,25 @@
 * along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
+use crate::{
+    action::Action,
+    event::{EventProxy, UserEvent},
+};
 use libc::{self, WNOHANG};
 use signal_hook::{consts::signal::*, iterator::Signals};
+use spdlog::info;
 use std::thread;
 
-pub fn setup_signal_handlers() -> anyhow::Result<()> {
-    let mut signals = Signals::new([SIGHUP, SIGCHLD])?;
+/// Handles signals on a separate thread; termination requests are passed to
+/// the event loop so that peyvand shuts down as if the user had quit.
+pub fn setup_signal_handlers(proxy: EventProxy) -> anyhow::Result<()> {
+    let mut signals = Signals::new([SIGHUP, SIGCHLD, SIGTERM, SIGINT])?;
     thread::spawn(move || {
         for signal in signals.forever() {
             match signal {
                 SIGHUP => handle_sighup(),
                 SIGCHLD => handle_sigchld(),
+                SIGTERM | SIGINT => handle_termination(&proxy),
                 _ => unreachable!(),
             }
         }
@@ -37,6 +45,17 @@ pub fn setup_signal_handlers() -> anyhow::Result<()> {
     Ok(())
 }
 
+fn handle_termination(proxy: &EventProxy) {
+    info!("Received termination signal");
+    if proxy
+        .send_event(UserEvent::Action(Action::Exit, 1))
+        .is_err()
+    {
+        // The event loop is already gone.
+        std::process::exit(0);
+    }
+}
+
 fn handle_sigchld() {
     let mut status = 0;
     println!("Received SIGCHLD");
@@ -19,55 +19,128 @@
 * along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use std::sync::mpsc;
+use anyhow::Context;
 use wry::http::Request;
 
 use crate::{
-    action::Action,
+    action::{Action, MAX_COUNT},
     agent,
     args::Args,
-    cookie::CookieManager,
-    hint::Hint,
-    history::History,
+    bookmark::BookmarkStore,
+    cmdline::{self, Cmdline, CommandHistory},
+    command,
+    config::{self, Config, Settings},
+    cookie::{self, CookieManager, CookieRule, CookieRules},
+    download::{self, DownloadEvent, Downloads},
+    event::{EventProxy, UserEvent},
+    hint::{self, Hint, HintAction, HintTarget},
+    history::{self, History, HistoryStore, Navigation, SameDocument, Traversal},
+    ipc::Ipc,
     key::{KeyMode, KeybindingManager},
+    mark::{self, Marks, PREVIOUS_MARK},
+    overlay::Overlay,
+    script::{Eval, Script},
     search::Search,
+    session::{
+        Session, SessionStore, TabSession, WindowSession, AUTOSAVE_SESSION, DEFAULT_SESSION,
+        LAST_SESSION,
+    },
     statusbar::Statusbar,
-    url::Url,
+    tab::{self, Tab, TabBar, TabId},
+    url::{self, Suggestion, SuggestionKind, Url},
+    window::{self, BrowserWindow},
 };
 use arboard::Clipboard;
-use spdlog::{debug, error};
-use std::sync::mpsc::Sender;
+use spdlog::{debug, error, info, warn};
+use std::time::{Duration, Instant};
 use tao::{
-    event_loop::EventLoop,
-    window::{Window, WindowBuilder},
+    event_loop::{EventLoop, EventLoopWindowTarget},
+    window::{Window, WindowBuilder, WindowId},
+};
+use wry::{
+    cookie::time::OffsetDateTime, NewWindowFeatures, NewWindowResponse, PageLoadEvent, WebView,
+    WebViewBuilder,
 };
-use wry::WebViewBuilder;
-
-const SCROLL_STEP: i32 = 40;
 
-fn make_ipc_handler(tx: Sender<Action>) -> impl Fn(Request<String>) + 'static {
+fn make_ipc_handler(ipc: Ipc, proxy: EventProxy, id: TabId) -> impl Fn(Request<String>) + 'static {
     move |req: Request<String>| {
-        if let Some(cmd) = req.body().strip_prefix("command:") {
-            tx.send(Action::NormalMode).ok();
-            match cmd {
-                "q" => {
-                    tx.send(Action::Exit).ok();
-                }
-                "h" | "help" => {
-                    tx.send(Action::ShowHelp).ok();
+        let Some(body) = ipc.verify(&req) else {
+            return;
+        };
+        let send = |action: Action, count: usize| {
+            proxy.send_event(UserEvent::Action(action, count)).ok();
+        };
+        if let Some(cmd) = body.strip_prefix("command:") {
+            send(Action::NormalMode, 1);
+            send(Action::Command(cmd.to_string()), 1);
+            return;
+        }
+        if let Some(line) = body.strip_prefix("complete:") {
+            send(Action::Complete(line.to_string()), 1);
+            return;
+        }
+        if let Some(input) = body.strip_prefix("url-complete:") {
+            send(Action::UrlComplete(input.to_string()), 1);
+            return;
+        }
+        if let Some(search) = body.strip_prefix("search:") {
+            send(Action::Search(search.to_string()), 1);
+            return;
+        }
+        if let Some(offset) = body.strip_prefix("scroll:") {
+            match offset
+                .split_once(',')
+                .and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)))
+            {
+                Some((x, y)) => {
+                    proxy.send_event(UserEvent::Scrolled(id, x, y)).ok();
                 }
-                _ => {
-                    error!("Unknown command: {}", cmd);
+                None => error!("Malformed scroll message: {}", offset),
+            }
+            return;
+        }
+        if let Some(count) = body.strip_prefix("hints:") {
+            match count.parse() {
+                Ok(count) => {
+                    proxy.send_event(UserEvent::Hints(id, count)).ok();
                 }
-            };
+                Err(_) => error!("Malformed hints message: {}", count),
+            }
             return;
         }
-        if let Some(search) = req.body().strip_prefix("search:") {
-            tx.send(Action::Search(search.to_string())).ok();
+        if let Some(hinted) = body.strip_prefix("hinted:") {
+            match hinted
+                .split_once(':')
+                .and_then(|(action, value)| Some((action.parse().ok()?, value)))
+            {
+                Some((action, value)) => send(Action::Hinted(action, value.to_string()), 1),
+                None => error!("Malformed hinted message: {}", hinted),
+            }
+            return;
+        }
+        if body == "user-navigation" {
+            proxy.send_event(UserEvent::UserNavigation(id)).ok();
+            return;
+        }
+        if let Some(location) = body.strip_prefix("location:") {
+            match location.split_once(':') {
+                Some((change, url)) => match change.parse() {
+                    Ok(change) => {
+                        let event = UserEvent::SameDocument(id, change, url.to_string());
+                        proxy.send_event(event).ok();
+                    }
+                    Err(_) => error!("Unknown location change: {}", change),
+                },
+                None => error!("Malformed location message: {}", location),
+            }
             return;
         }
 
-        let mut parts = req.body().splitn(2, ':');
+        let Some((count, body)) = split_count(body) else {
+            error!("Malformed count in message: {}", body);
+            return;
+        };
+        let mut parts = body.splitn(2, ':');
         let action_str = parts.next().unwrap();
         let param = parts.next();
 
@@ -75,64 +148,264 @@ fn make_ipc_handler(tx: Sender<Action>) -> impl Fn(Request<String>) + 'static {
             Ok(action) => match action {
                 Action::ChangeURL(_) => {
                     if let Some(url) = param {
-                        tx.send(Action::ChangeURL(url.to_string())).ok();
+                        send(Action::ChangeURL(url.to_string()), 1);
                     }
                 }
-                _ => {
-                    tx.send(action).ok();
+                Action::MarkSet(_)
+                | Action::MarkJump(_)
+                | Action::QuickmarkSet(_)
+                | Action::QuickmarkOpen(_) => match param {
+                    Some(name) => send(action.with_args(&[name.to_string()]).unwrap(), count),
+                    None => error!("No mark given for {}", action_str),
+                },
+                Action::HintWindow(_)
+                | Action::HintYankUrl(_)
+                | Action::HintYankText(_)
+                | Action::HintDownload(_)
+                | Action::HintFocus(_)
+                | Action::HintHover(_)
+                | Action::HintRightClick(_)
+                | Action::HintExternal(_)
+                | Action::HintBackground(_)
+                | Action::HintSelectOpen(_)
+                | Action::HintSelectYank(_) => {
+                    let args: Vec<String> = param.map(String::from).into_iter().collect();
+                    match action.with_args(&args) {
+                        Ok(action) => send(action, count),
+                        Err(msg) => error!("{}", msg),
+                    }
                 }
+                _ => send(action, count),
             },
             Err(_) => error!("Unknown action: {}", action_str),
         }
     }
 }
 
-fn make_navigation_handler(nav_tx: Sender<String>) -> impl Fn(String) -> bool + 'static {
-    move |url: String| {
-        nav_tx.send(url.to_string()).ok();
-        true
+/// Splits a Vim-style count prefix off a message, e.g. `5scroll-down`.
+///
+/// Returns `None` unless the count is only digits and directly followed by
+/// an action name. Counts above `MAX_COUNT` are clamped to it.
+fn split_count(body: &str) -> Option<(usize, &str)> {
+    let rest = body.trim_start_matches(|c: char| c.is_ascii_digit());
+    if !rest.starts_with(|c: char| c.is_ascii_lowercase()) {
+        return None;
+    }
+    let digits = &body[..body.len() - rest.len()];
+    let count = if digits.is_empty() {
+        1
+    } else {
+        // Only digits are left, so parsing fails on overflow alone.
+        digits.parse().unwrap_or(MAX_COUNT).min(MAX_COUNT)
+    };
+    Some((count.max(1), rest))
+}
+
+/// A snapshot of a tab, as saved in sessions and kept for reopening it.
+fn save_tab(tab: &Tab) -> TabSession {
+    TabSession {
+        entries: tab.history.entries().to_vec(),
+        index: tab.history.index(),
+        title: tab.title.clone(),
+        scroll: tab.pending_scroll.unwrap_or(tab.scroll),
     }
 }
 
+fn make_page_load_handler(
+    proxy: EventProxy,
+    id: TabId,
+) -> impl Fn(PageLoadEvent, String) + 'static {
+    move |event: PageLoadEvent, url: String| {
+        let event = match event {
+            PageLoadEvent::Started => UserEvent::LoadStarted(id, url),
+            PageLoadEvent::Finished => UserEvent::LoadFinished(id, url),
+        };
+        proxy.send_event(event).ok();
+    }
+}
+
+fn make_title_handler(proxy: EventProxy, id: TabId) -> impl Fn(String) + 'static {
+    move |title: String| {
+        proxy.send_event(UserEvent::TitleChanged(id, title)).ok();
+    }
+}
+
+/// Opens pages that ask for a new window (`target="_blank"`, `window.open`)
+/// in a peyvand window instead.
+fn make_new_window_handler(
+    proxy: EventProxy,
+) -> impl Fn(String, NewWindowFeatures) -> NewWindowResponse + Send + Sync + 'static {
+    move |url: String, _features: NewWindowFeatures| {
+        proxy.send_event(UserEvent::NewWindow(url)).ok();
+        NewWindowResponse::Deny
+    }
+}
+
+/// Caps the number of rows sent to a picker overlay.
+const MAX_PICKER_ITEMS: usize = 1000;
+
+/// Caps the number of suggestions under the URL bar.
+const MAX_URL_SUGGESTIONS: usize = 15;
+
+/// Caps the number of tabs opened at once from selected links.
+const MAX_BACKGROUND_TABS: usize = 20;
+
+/// Caps the number of windows pages may open within `PAGE_WINDOW_PERIOD`.
+const MAX_PAGE_WINDOWS: usize = 3;
+
+const PAGE_WINDOW_PERIOD: Duration = Duration::from_secs(60);
+
+/// Caps the number of closed tabs that can be reopened.
+const MAX_CLOSED_TABS: usize = 20;
+
 pub struct State {
-    pub window: Window,
-    pub webview: wry::WebView,
-    pub history: History,
+    pub windows: Vec<BrowserWindow>,
+    pub active: usize,
+    next_tab_id: TabId,
+    proxy: EventProxy,
+    agent: String,
     pub key_mode: KeyMode,
     pub cookie_mgr: CookieManager,
     pub key_mgr: KeybindingManager,
     pub clipboard: Clipboard,
+    pub settings: Settings,
+    pub cmd_history: CommandHistory,
+    pub history_store: HistoryStore,
+    pub marks: Marks,
+    pub bookmarks: BookmarkStore,
+    pub sessions: SessionStore,
+    last_autosave: Instant,
+    /// The tab last opened in the background, which the next one follows.
+    last_background_tab: Option<TabId>,
+    downloads: Downloads,
+    /// The suggestions last sent to the URL bar, which it picks by index.
+    url_suggestions: Vec<Suggestion>,
+    /// Recently closed tabs, the last closed one at the end.
+    closed_tabs: Vec<TabSession>,
+    /// When pages last opened windows, within `PAGE_WINDOW_PERIOD`.
+    page_windows: Vec<Instant>,
+    pub ipc: Ipc,
 }
 
 impl State {
-    pub fn new<T, S: AsRef<str>>(
+    pub fn new(
         args: &Args,
-        event_loop: &EventLoop<T>,
-        url: S,
-    ) -> anyhow::Result<(Self, mpsc::Receiver<Action>, mpsc::Receiver<String>)> {
+        config: &Config,
+        event_loop: &EventLoop<UserEvent>,
+    ) -> anyhow::Result<Self> {
+        let settings = config.settings.clone();
+        let url = match &args.url {
+            Some(input) => url::resolve(input, &settings),
+            None => settings.homepage.clone(),
+        };
+
         let clipboard = Clipboard::new().unwrap();
 
-        let (cmd_tx, cmd_rx) = mpsc::channel::<Action>();
-        let ipc_handler = make_ipc_handler(cmd_tx.clone());
+        let agent = match args.user_agent.as_ref().or(settings.user_agent.as_ref()) {
+            Some(agent) => agent.clone(),
+            None => agent::default_user_agent().to_string(),
+        };
 
-        let (nav_tx, nav_rx) = mpsc::channel::<String>();
-        let nav_handler = make_navigation_handler(nav_tx.clone());
+        let key_mgr = KeybindingManager::new(Some(&config.keybindings))
+            .map_err(anyhow::Error::msg)
+            .context("Invalid keybindings in config")?;
 
-        let window = WindowBuilder::new()
-            .with_title(url.as_ref())
-            .build(event_loop)?;
+        let data_dir = config::data_dir();
+        let cookie_policy = args.cookie_policies.unwrap_or(settings.cookie_policy);
+        let cookie_rules = CookieRules::load(
+            data_dir.as_ref().map(|dir| dir.join("cookie-rules.json")),
+            &settings.cookie_rules,
+        );
+        let cookie_mgr = CookieManager::new(
+            args.cookiefile.clone(),
+            cookie_policy,
+            cookie_rules,
+            settings.block_third_party_cookies,
+        );
+        let cmd_history =
+            CommandHistory::load(data_dir.as_ref().map(|dir| dir.join("command_history")));
+        let mut history_store =
+            HistoryStore::load(data_dir.as_ref().map(|dir| dir.join("history.json")));
+        if settings.history_days > 0 {
+            history_store.prune(settings.history_days);
+        }
+        let marks = Marks::load(data_dir.as_ref().map(|dir| dir.join("marks.json")));
+        let bookmarks =
+            BookmarkStore::load(data_dir.as_ref().map(|dir| dir.join("bookmarks.json")));
+        let sessions = SessionStore::new(data_dir.as_ref().map(|dir| dir.join("sessions")));
 
-        let agent = match &args.user_agent {
-            Some(agent) => agent.as_str(),
-            None => agent::default_user_agent(),
+        let mut state = Self {
+            windows: Vec::new(),
+            active: 0,
+            next_tab_id: 0,
+            proxy: event_loop.create_proxy(),
+            agent,
+            key_mode: KeyMode::Normal,
+            cookie_mgr,
+            key_mgr,
+            clipboard,
+            settings,
+            cmd_history,
+            history_store,
+            marks,
+            bookmarks,
+            sessions,
+            last_autosave: Instant::now(),
+            last_background_tab: None,
+            downloads: Downloads::new(),
+            url_suggestions: Vec::new(),
+            closed_tabs: Vec::new(),
+            page_windows: Vec::new(),
+            ipc: Ipc::new(),
         };
 
+        let restored = args
+            .restore
+            .as_deref()
+            .is_some_and(|name| state.restore(event_loop, name));
+        if !restored {
+            state.open_window(event_loop, &url)?;
+        } else if args.url.is_some() {
+            state.tab_new(&url);
+        }
+        state.cookie_mgr.load_cookies(state.webview())?;
+        Ok(state)
+    }
+
+    /// Restores the session given with `--restore`. Without a name, the
+    /// crash-recovery session wins over the one saved on exit.
+    fn restore(&mut self, target: &EventLoopWindowTarget<UserEvent>, name: &str) -> bool {
+        let name = match name {
+            "" if self.sessions.exists(AUTOSAVE_SESSION) => AUTOSAVE_SESSION,
+            "" => LAST_SESSION,
+            name => name,
+        };
+        info!("Restoring session {}", name);
+        self.sessions
+            .load(name)
+            .and_then(|session| self.open_session(target, &session))
+            .inspect_err(|e| error!("Failed to restore session {}: {:#}", name, e))
+            .is_ok()
+    }
+
+    fn next_tab_id(&mut self) -> TabId {
+        self.next_tab_id += 1;
+        self.next_tab_id
+    }
+
+    /// Creates a webview for a new tab in `window`, hidden until it is
+    /// selected.
+    fn build_tab(&self, id: TabId, window: &Window, url: &str) -> anyhow::Result<Tab> {
+        self.build_tab_with(id, window, History::new(url))
+    }
+
+    /// Creates a webview for a tab whose back/forward list is already known,
+    /// loading its current page.
+    fn build_tab_with(&self, id: TabId, window: &Window, history: History) -> anyhow::Result<Tab> {
         let statusbar = Statusbar::new();
         let statusbar_js = statusbar.get_statusbar();
-        // let config: KeybindingConfig = toml::from_str(toml_str).unwrap();
-        let key_mgr = KeybindingManager::new(None).unwrap();
 
-        let keybinding_js = key_mgr.export_full_js();
+        let keybinding_js = self.key_mgr.export_full_js();
 
         let url_mgr = Url::new();
         let url_js = url_mgr.get_url();
@@ -141,157 +414,1227 @@ impl State {
 
         let hint_js = Hint::get_js();
 
-        let inject = format!("{statusbar_js}\n{url_js}\n{search_js}\n{hint_js}\n{keybinding_js}");
+        let overlay_js = Overlay::get_js();
+
+        let cmdline_js = Cmdline::get_js();
+
+        let history_js = History::get_js();
+
+        let script_js = Script::get_js();
+
+        let tabbar_js = TabBar::get_js();
+
+        let session_js = Session::get_js();
+
+        let inject = self.ipc.wrap_script(&format!(
+            "{script_js}\n{statusbar_js}\n{url_js}\n{search_js}\n{hint_js}\n{overlay_js}\n{cmdline_js}\n{history_js}\n{tabbar_js}\n{session_js}\n{keybinding_js}"
+        ));
         // std::fs::write("inject.js", &inject).unwrap();
 
         let builder = WebViewBuilder::new()
-            .with_url(url.as_ref())
-            .with_user_agent(agent)
-            .with_ipc_handler(ipc_handler)
+            .with_url(history.current())
+            .with_user_agent(&self.agent)
+            .with_bounds(window::bounds(window))
+            .with_visible(false)
+            .with_ipc_handler(make_ipc_handler(self.ipc.clone(), self.proxy.clone(), id))
             .with_initialization_script(inject)
-            .with_navigation_handler(nav_handler);
-
-        let webview = builder.build(&window)?;
-
-        let cookie_mgr = CookieManager::new(args.cookiefile.clone(), args.cookie_policies.clone());
-        cookie_mgr.load_cookies(&webview)?;
-
-        let history = History::new(url.as_ref());
-        Ok((
-            Self {
-                webview,
-                window,
-                history,
-                key_mode: KeyMode::Normal,
-                cookie_mgr,
-                key_mgr,
-                clipboard,
-            },
-            cmd_rx,
-            nav_rx,
-        ))
+            .with_on_page_load_handler(make_page_load_handler(self.proxy.clone(), id))
+            .with_document_title_changed_handler(make_title_handler(self.proxy.clone(), id))
+            .with_new_window_req_handler(make_new_window_handler(self.proxy.clone()))
+            .with_download_started_handler(download::make_started_handler(
+                self.proxy.clone(),
+                id,
+                self.downloads.dir(),
+            ))
+            .with_download_completed_handler(download::make_completed_handler(
+                self.proxy.clone(),
+                id,
+            ));
+
+        let webview = builder.build_as_child(window)?;
+        Ok(Tab {
+            id,
+            webview,
+            history,
+            title: String::new(),
+            scroll: (0.0, 0.0),
+            pending_scroll: None,
+            pending_hint: None,
+        })
+    }
+
+    /// The focused window.
+    pub fn win(&self) -> &BrowserWindow {
+        &self.windows[self.active]
+    }
+
+    pub fn win_mut(&mut self) -> &mut BrowserWindow {
+        &mut self.windows[self.active]
+    }
+
+    pub fn tab(&self) -> &Tab {
+        self.win().tab()
+    }
+
+    pub fn tab_mut(&mut self) -> &mut Tab {
+        self.win_mut().tab_mut()
+    }
+
+    pub fn webview(&self) -> &WebView {
+        self.win().webview()
+    }
+
+    fn window_index(&self, id: WindowId) -> Option<usize> {
+        self.windows.iter().position(|win| win.id() == id)
+    }
+
+    /// Finds the window and the position within it of a tab.
+    fn locate_tab(&self, id: TabId) -> Option<(usize, usize)> {
+        self.windows
+            .iter()
+            .enumerate()
+            .find_map(|(w, win)| win.tab_index(id).map(|t| (w, t)))
     }
 }
 
 impl State {
-    pub fn set_url<S: AsRef<str>>(&mut self, url: S) {
-        self.history.push(url.as_ref());
-        self.window.set_title(self.history.current());
+    pub fn go_back(&mut self, count: usize) {
+        self.traverse(-(count as isize));
+    }
+
+    pub fn go_forward(&mut self, count: usize) {
+        self.traverse(count as isize);
+    }
+
+    fn traverse(&mut self, steps: isize) {
+        match self.tab_mut().history.go(steps) {
+            Some(Traversal::Steps(steps)) => self.webview().call("history.go", (steps,)),
+            Some(Traversal::Replace(url)) => self.webview().call("window.location.replace", (url,)),
+            None => {}
+        }
+    }
+
+    pub fn load_started(&mut self, id: TabId, url: String) {
+        if let Some((w, t)) = self.locate_tab(id) {
+            let tab = &mut self.windows[w].tabs[t];
+            let navigation = tab.history.load_started(&url);
+            debug!("Tab {} loading {} ({:?})", id, url, navigation);
+            self.cookie_mgr.visit(&url);
+            // The new document reports its own title and scroll offset.
+            tab.title.clear();
+            tab.scroll = (0.0, 0.0);
+            tab.pending_hint = None;
+            self.windows[w].update_title();
+        }
+    }
+
+    pub fn load_finished(&mut self, id: TabId, url: String) {
+        if self.downloads.contains(id) {
+            if let Some(url) = self.downloads.loaded(id) {
+                self.echo_error(&format!("Nothing to download at {url}"));
+            }
+            return;
+        }
+        let Some((w, t)) = self.locate_tab(id) else {
+            return;
+        };
+        let tab = &mut self.windows[w].tabs[t];
+        tab.history.load_finished(&url);
+        self.history_store.record_visit(tab.history.current());
+        let title = tab.title.clone();
+        self.history_store.set_title(tab.history.current(), &title);
+
+        if let Some((x, y)) = tab.pending_scroll.take() {
+            tab.webview.call("window.scrollTo", (x, y));
+        }
+        // A new document starts out in normal mode.
+        tab.webview.call_locked(
+            self.ipc.token(),
+            "window.setMode",
+            (self.key_mode.as_ref(),),
+        );
+
+        let win = &self.windows[w];
+        self.reload_bindings_in(&win.tabs[t].webview);
+        win.update_title();
+        if let Err(e) = self.cookie_mgr.enforce(&win.tabs[t].webview) {
+            error!("Failed to enforce the cookie rules: {}", e);
+        }
+    }
+
+    /// Keeps the next load of a tab from being taken for a redirect when
+    /// the page reported that the user navigated.
+    pub fn user_navigation(&mut self, id: TabId) {
+        if let Some((w, t)) = self.locate_tab(id) {
+            self.windows[w].tabs[t].history.expect_user_navigation();
+        }
+    }
+
+    pub fn same_document(&mut self, id: TabId, change: SameDocument, url: String) {
+        if let Some((w, t)) = self.locate_tab(id) {
+            let history = &mut self.windows[w].tabs[t].history;
+            if history.same_document(change, &url) == Some(Navigation::User) {
+                self.history_store.record_visit(&url);
+            }
+            self.windows[w].update_title();
+        }
+    }
+
+    pub fn title_changed(&mut self, id: TabId, title: String) {
+        if let Some((w, t)) = self.locate_tab(id) {
+            let tab = &mut self.windows[w].tabs[t];
+            self.history_store.set_title(tab.history.current(), &title);
+            tab.title = title;
+            self.windows[w].update_title();
+        }
+    }
+
+    pub fn scrolled(&mut self, id: TabId, x: f64, y: f64) {
+        if let Some((w, t)) = self.locate_tab(id) {
+            self.windows[w].tabs[t].scroll = (x, y);
+        }
+    }
+
+    /// Hints the `target` elements of the current page to act on one.
+    pub fn hint(&mut self, action: HintAction, target: HintTarget) {
+        self.set_key_mode(KeyMode::Hint);
+        self.tab_mut().pending_hint = Some(action);
+        let alphabet: String = self.hint_alphabet().into_iter().collect();
+        self.webview().call_locked(
+            self.ipc.token(),
+            "window.showHints",
+            (action.as_ref(), target.as_ref(), alphabet),
+        );
+    }
+
+    fn hint_alphabet(&self) -> Vec<char> {
+        // The alphabet was validated when it was set.
+        hint::parse_alphabet(&self.settings.hint_alphabet).unwrap_or_default()
+    }
+
+    /// Acts on a hint the page sent back, with the URL or text of the
+    /// element. Only the action of the hints shown in the tab is taken, and
+    /// only once unless the hints stay up for more.
+    pub fn hinted(
+        &mut self,
+        target: &EventLoopWindowTarget<UserEvent>,
+        action: HintAction,
+        value: &str,
+    ) {
+        let tab = self.tab_mut();
+        if tab.pending_hint != Some(action) {
+            warn!("Ignored a {} hint that was not shown", action);
+            return;
+        }
+        if action != HintAction::Background {
+            tab.pending_hint = None;
+        }
+        match action {
+            HintAction::Window => self.window_new(target, value),
+            HintAction::YankUrl => {
+                let _ = self.clipboard.set_text(value);
+                self.echo(&format!("Yanked {value}"));
+            }
+            HintAction::YankText => {
+                let _ = self.clipboard.set_text(value);
+                self.echo(&format!("Yanked {} characters", value.chars().count()));
+            }
+            HintAction::External if !url::is_web(value) => self.echo_error(&format!(
+                "Only http and https links open externally, not {value}"
+            )),
+            HintAction::External => self.open_external(value),
+            HintAction::Download => self.download(value),
+            HintAction::Background => {
+                self.tab_new_background(value);
+            }
+            HintAction::SelectOpen => {
+                let urls: Vec<&str> = value.lines().collect();
+                let opened = urls
+                    .iter()
+                    .take(MAX_BACKGROUND_TABS)
+                    .filter(|url| self.tab_new_background(url))
+                    .count();
+                if urls.len() > MAX_BACKGROUND_TABS {
+                    self.echo(&format!(
+                        "Opened {opened} tabs for the first {MAX_BACKGROUND_TABS} of {} links",
+                        urls.len()
+                    ));
+                } else {
+                    self.echo(&format!("Opened {opened} tabs"));
+                }
+            }
+            HintAction::SelectYank => {
+                let _ = self.clipboard.set_text(value);
+                self.echo(&format!("Yanked {} URLs", value.lines().count()));
+            }
+            action => error!("The page handles {} hints itself", action),
+        }
+    }
+
+    /// Opens `url` with the `external-command`, in place of its `{}` or
+    /// after its arguments.
+    pub fn open_external(&self, url: &str) {
+        let template = &self.settings.external_command;
+        let mut args = match command::split_args(template) {
+            Ok(args) => args,
+            Err(msg) => return self.echo_error(&format!("Invalid external-command: {msg}")),
+        };
+        if template.contains("{}") {
+            args.iter_mut()
+                .for_each(|arg| *arg = arg.replace("{}", url));
+        } else {
+            args.push(url.to_string());
+        }
+        let Some((program, args)) = args.split_first() else {
+            return self.echo_error("No external-command set");
+        };
+        match std::process::Command::new(program).args(args).spawn() {
+            Ok(mut child) => {
+                // Reap the program when it exits.
+                std::thread::spawn(move || child.wait());
+                self.echo(&format!("Opened {url} with {program}"));
+            }
+            Err(e) => self.echo_error(&format!("Failed to run {program}: {e}")),
+        }
+    }
+
+    /// Downloads `url` in a hidden webview.
+    pub fn download(&mut self, url: &str) {
+        if !url::is_web(url) {
+            return self.echo_error(&format!("Cannot download {url}"));
+        }
+        let id = self.next_tab_id();
+        let result = WebViewBuilder::new()
+            .with_url(url)
+            .with_user_agent(&self.agent)
+            .with_visible(false)
+            .with_on_page_load_handler(make_page_load_handler(self.proxy.clone(), id))
+            .with_download_started_handler(download::make_started_handler(
+                self.proxy.clone(),
+                id,
+                self.downloads.dir(),
+            ))
+            .with_download_completed_handler(download::make_completed_handler(
+                self.proxy.clone(),
+                id,
+            ))
+            .build_as_child(&self.win().window);
+        match result {
+            Ok(webview) => self.downloads.add(id, url, webview),
+            Err(e) => self.echo_error(&format!("Failed to download {url}: {e}")),
+        }
+    }
+
+    pub fn download_event(&mut self, id: TabId, event: DownloadEvent) {
+        match event {
+            DownloadEvent::Started(path) => {
+                self.downloads.started(id);
+                self.echo(&format!("Downloading to {}", path.display()));
+            }
+            DownloadEvent::Finished(path, success) => {
+                self.downloads.finished(id);
+                let path = path.map(|path| path.display().to_string());
+                match (success, path) {
+                    (true, Some(path)) => self.echo(&format!("Downloaded {path}")),
+                    (true, None) => self.echo("Download finished"),
+                    (false, _) => self.echo_error("Download failed"),
+                }
+            }
+        }
+    }
+
+    /// Sends the labels for the `count` hints the page of a tab found.
+    pub fn label_hints(&self, id: TabId, count: usize) {
+        let Some((w, t)) = self.locate_tab(id) else {
+            return;
+        };
+        let labels = hint::labels(
+            count,
+            &self.hint_alphabet(),
+            self.settings.hint_uniform_labels,
+        );
+        self.windows[w].tabs[t].webview.call_locked(
+            self.ipc.token(),
+            "window.labelHints",
+            (labels,),
+        );
+    }
+
+    pub fn resize(&self, id: WindowId) {
+        if let Some(w) = self.window_index(id) {
+            self.windows[w].resize();
+        }
+    }
+
+    pub fn focus(&mut self, id: WindowId) {
+        if let Some(w) = self.window_index(id) {
+            self.active = w;
+            self.set_key_mode(KeyMode::Normal);
+        }
+    }
+
+    /// Opens a window showing `url` and focuses it.
+    pub fn open_window(
+        &mut self,
+        target: &EventLoopWindowTarget<UserEvent>,
+        url: &str,
+    ) -> anyhow::Result<()> {
+        let win = self.build_window(
+            target,
+            &WindowSession {
+                tabs: vec![TabSession {
+                    entries: vec![url.to_string()],
+                    index: 0,
+                    title: String::new(),
+                    scroll: (0.0, 0.0),
+                }],
+                current: 0,
+            },
+        )?;
+        self.windows.push(win);
+        self.active = self.windows.len() - 1;
+        self.select_tab(self.win().current);
+        Ok(())
+    }
+
+    /// Creates a window with the tabs of a saved one.
+    fn build_window(
+        &mut self,
+        target: &EventLoopWindowTarget<UserEvent>,
+        saved: &WindowSession,
+    ) -> anyhow::Result<BrowserWindow> {
+        let window = WindowBuilder::new().build(target)?;
+        let mut win = BrowserWindow::new(window, self.ipc.clone());
+        for saved_tab in &saved.tabs {
+            let id = self.next_tab_id();
+            let tab = self.build_saved_tab(id, &win.window, saved_tab)?;
+            win.tabs.push(tab);
+        }
+        win.select(saved.current.min(win.tabs.len().saturating_sub(1)));
+        Ok(win)
+    }
+
+    /// Creates a tab from its saved back/forward list, title and scroll
+    /// position.
+    fn build_saved_tab(
+        &self,
+        id: TabId,
+        window: &Window,
+        saved: &TabSession,
+    ) -> anyhow::Result<Tab> {
+        let history = History::restore(saved.entries.clone(), saved.index);
+        let mut tab = self.build_tab_with(id, window, history)?;
+        tab.title = saved.title.clone();
+        tab.pending_scroll = Some(saved.scroll).filter(|&scroll| scroll != (0.0, 0.0));
+        Ok(tab)
+    }
+
+    /// Opens `url`, or the homepage when empty, in a new window.
+    pub fn window_new(&mut self, target: &EventLoopWindowTarget<UserEvent>, url: &str) {
+        let Some(url) = self.checked_url(url) else {
+            return;
+        };
+        if let Err(e) = self.open_window(target, &url) {
+            self.echo_error(&format!("Failed to open a window: {e}"));
+        }
+    }
+
+    /// Opens a window a page asked for, as long as it shows a web page and
+    /// pages have not opened too many windows lately.
+    pub fn page_window_new(&mut self, target: &EventLoopWindowTarget<UserEvent>, url: &str) {
+        if !url::is_web(url) {
+            return self.echo_error(&format!("Refused to open a window for {url}"));
+        }
+        let now = Instant::now();
+        self.page_windows
+            .retain(|&opened| now.duration_since(opened) < PAGE_WINDOW_PERIOD);
+        if self.page_windows.len() >= MAX_PAGE_WINDOWS {
+            return self.echo_error(&format!("Blocked a pop-up window for {url}"));
+        }
+        self.page_windows.push(now);
+        self.window_new(target, url);
+    }
+
+    /// Closes a window with all its tabs and returns whether any window is
+    /// left. The cookies are saved before the last one goes away.
+    pub fn close_window(&mut self, id: WindowId) -> bool {
+        let Some(w) = self.window_index(id) else {
+            return true;
+        };
+        if self.windows.len() == 1 {
+            self.exit();
+            return false;
+        }
+        self.windows.remove(w);
+        if self.active >= w {
+            self.active = self.active.saturating_sub(1);
+        }
+        let _ = self.webview().focus();
+        true
     }
 
-    pub fn go_back(&mut self) {
-        self.history.back();
-        self.window.set_title(self.history.current());
-        let _ = self.webview.evaluate_script("history.back();");
+    pub fn window_close(&mut self) -> bool {
+        self.close_window(self.win().id())
     }
 
-    pub fn go_forward(&mut self) {
-        self.history.forward();
-        self.window.set_title(self.history.current());
-        let _ = self.webview.evaluate_script("history.forward();");
+    /// Makes the tab at `index` of the focused window the visible one.
+    pub fn select_tab(&mut self, index: usize) {
+        self.win_mut().select(index);
+        self.set_key_mode(KeyMode::Normal);
     }
 
+    /// Opens `url`, or the homepage when empty, in a tab after the current one.
+    pub fn tab_new(&mut self, url: &str) {
+        let Some(url) = self.checked_url(url) else {
+            return;
+        };
+        let id = self.next_tab_id();
+        match self.build_tab(id, &self.win().window, &url) {
+            Ok(tab) => {
+                let index = self.win().current + 1;
+                self.win_mut().tabs.insert(index, tab);
+                self.select_tab(index);
+            }
+            Err(e) => self.echo_error(&format!("Failed to open a tab: {e}")),
+        }
+    }
+
+    /// Opens `url` in a tab without leaving the current one and returns
+    /// whether it did. Tabs opened one after another this way stay in that
+    /// order after the current tab.
+    pub fn tab_new_background(&mut self, url: &str) -> bool {
+        let Some(url) = self.checked_url(url) else {
+            return false;
+        };
+        let id = self.next_tab_id();
+        let tab = match self.build_tab(id, &self.win().window, &url) {
+            Ok(tab) => tab,
+            Err(e) => {
+                self.echo_error(&format!("Failed to open a tab: {e}"));
+                return false;
+            }
+        };
+        let win = self.win();
+        let index = self
+            .last_background_tab
+            .and_then(|id| win.tab_index(id))
+            .filter(|index| *index > win.current)
+            .unwrap_or(win.current)
+            + 1;
+        self.last_background_tab = Some(id);
+        let win = self.win_mut();
+        win.tabs.insert(index, tab);
+        win.show_tabs();
+        true
+    }
+
+    pub fn tab_duplicate(&mut self) {
+        let url = self.tab().history.current().to_string();
+        self.tab_new(&url);
+    }
+
+    /// Closes the current tab and returns whether any tab is left in the
+    /// focused window.
+    pub fn tab_close(&mut self) -> bool {
+        let win = self.win_mut();
+        if win.tabs.len() == 1 {
+            return false;
+        }
+        let tab = win.tabs.remove(win.current);
+        let index = win.current.min(win.tabs.len() - 1);
+        if self.closed_tabs.len() == MAX_CLOSED_TABS {
+            self.closed_tabs.remove(0);
+        }
+        self.closed_tabs.push(save_tab(&tab));
+        self.select_tab(index);
+        true
+    }
+
+    /// Reopens the last closed tab after the current one, with its
+    /// back/forward list.
+    pub fn tab_undo(&mut self) {
+        let Some(saved) = self.closed_tabs.pop() else {
+            return self.echo_error("No closed tab to reopen");
+        };
+        let id = self.next_tab_id();
+        match self.build_saved_tab(id, &self.win().window, &saved) {
+            Ok(tab) => {
+                let index = self.win().current + 1;
+                self.win_mut().tabs.insert(index, tab);
+                self.select_tab(index);
+            }
+            Err(e) => self.echo_error(&format!("Failed to reopen the tab: {e}")),
+        }
+    }
+
+    pub fn tab_next(&mut self, count: usize) {
+        let win = self.win();
+        self.select_tab(tab::cycle(win.current, win.tabs.len(), count as isize));
+    }
+
+    pub fn tab_prev(&mut self, count: usize) {
+        let win = self.win();
+        self.select_tab(tab::cycle(win.current, win.tabs.len(), -(count as isize)));
+    }
+
+    pub fn tab_move(&mut self, position: &str) {
+        let win = self.win();
+        match tab::move_target(win.current, win.tabs.len(), position) {
+            Ok(target) => self.win_mut().move_tab(target),
+            Err(msg) => self.echo_error(&msg),
+        }
+    }
+
+    pub fn tab_shift(&mut self, steps: isize) {
+        let win = self.win_mut();
+        let target = (win.current as isize + steps).clamp(0, win.tabs.len() as isize - 1);
+        win.move_tab(target as usize);
+    }
+
+    /// Selects a tab by its 1-based number in the tab bar.
+    pub fn buffer(&mut self, number: usize) {
+        match number.checked_sub(1).filter(|&i| i < self.win().tabs.len()) {
+            Some(index) => self.select_tab(index),
+            None => self.echo_error(&format!("No buffer {number}")),
+        }
+    }
+
+    pub fn show_buffers(&self) {
+        let win = self.win();
+        let (items, commands): (Vec<String>, Vec<String>) = win
+            .tabs
+            .iter()
+            .enumerate()
+            .map(|(i, tab)| {
+                let item = format!("{:>3}  {}  {}", i + 1, tab.title(), tab.history.current());
+                (item, format!("buffer {}", i + 1))
+            })
+            .unzip();
+        self.webview().call_locked(
+            self.ipc.token(),
+            "window.showPicker",
+            ("Buffers", items, win.current, commands),
+        );
+    }
     pub fn get_key_mode(&self) -> KeyMode {
         self.key_mode
     }
 
     pub fn set_key_mode(&mut self, mode: KeyMode) {
         self.key_mode = mode;
-
-        let script = format!("window.appState = {{ mode: '{}' }};", mode.as_ref());
+        if mode != KeyMode::Hint {
+            self.tab_mut().pending_hint = None;
+        }
 
         debug!("Mode: {:#?}", mode);
-        let _ = self.webview.evaluate_script(&script);
+        self.webview()
+            .call_locked(self.ipc.token(), "window.setMode", (mode.as_ref(),));
+    }
+
+    pub fn scroll_down(&self, count: usize) {
+        self.scroll_by(self.settings.scroll_step.saturating_mul(count as i32));
     }
 
-    pub fn scroll_down(&self) {
-        let script = format!("window.scrollBy(0, {});", SCROLL_STEP);
-        let _ = self.webview.evaluate_script(&script);
+    pub fn scroll_up(&self, count: usize) {
+        self.scroll_by(self.settings.scroll_step.saturating_mul(-(count as i32)));
     }
 
-    pub fn scroll_up(&self) {
-        let script = format!("window.scrollBy(0, -{});", SCROLL_STEP);
-        let _ = self.webview.evaluate_script(&script);
+    pub fn scroll_top(&mut self) {
+        self.remember_position();
+        self.webview().call("window.scrollTo", (0, 0));
     }
 
-    pub fn scroll_top(&self) {
-        let _ = self.webview.evaluate_script("window.scrollTo(0, 0);");
+    pub fn scroll_bottom(&mut self) {
+        self.remember_position();
+        self.webview()
+            .call_locked(self.ipc.token(), "window.scrollToBottom", ());
     }
 
-    pub fn scroll_bottom(&self) {
-        let _ = self
-            .webview
-            .evaluate_script("window.scrollTo(0, document.body.scrollHeight);");
+    pub fn scroll_half_down(&self, count: usize) {
+        self.scroll_by(self.settings.scroll_step.saturating_mul(6 * count as i32));
     }
 
-    pub fn scroll_half_down(&self) {
-        let script = format!("window.scrollBy(0, {});", SCROLL_STEP * 6);
-        let _ = self.webview.evaluate_script(&script);
+    pub fn scroll_half_up(&self, count: usize) {
+        self.scroll_by(self.settings.scroll_step.saturating_mul(-6 * count as i32));
     }
 
-    pub fn scroll_half_up(&self) {
-        let script = format!("window.scrollBy(0, -{});", SCROLL_STEP * 6);
-        let _ = self.webview.evaluate_script(&script);
+    fn scroll_by(&self, dy: i32) {
+        self.webview().call("window.scrollBy", (0, dy));
     }
 
-    pub fn exit(&self) {
-        let _ = self.cookie_mgr.save_cookies(&self.webview);
+    pub fn mark_set(&mut self, name: &str) {
+        match Marks::parse_name(name) {
+            Ok(name) => {
+                let tab = self.windows[self.active].tab();
+                self.marks.set(name, tab.history.current(), tab.scroll);
+                self.echo(&format!("Mark {name} set"));
+            }
+            Err(msg) => self.echo_error(&msg),
+        }
+    }
+
+    /// Jumps to a mark, loading its page first if it was set on another one.
+    pub fn mark_jump(&mut self, name: &str) {
+        let name = match Marks::parse_name(name) {
+            Ok(name) => name,
+            Err(msg) => return self.echo_error(&msg),
+        };
+        let Some(mark) = self.marks.get(name, self.tab().history.current()) else {
+            return self.echo_error(&format!("Mark not set: {name}"));
+        };
+        self.remember_position();
+        if mark::page(&mark.url) == mark::page(self.tab().history.current()) {
+            let (x, y) = mark.scroll;
+            self.webview().call("window.scrollTo", (x, y));
+        } else {
+            self.change_url(&mark.url);
+            self.tab_mut().pending_scroll = Some(mark.scroll);
+        }
+    }
+
+    /// Sets the `''` mark before a large jump.
+    fn remember_position(&mut self) {
+        let tab = self.windows[self.active].tab();
+        self.marks
+            .set(PREVIOUS_MARK, tab.history.current(), tab.scroll);
+    }
+
+    pub fn exit(&mut self) {
+        let _ = self.cookie_mgr.save_cookies(self.webview());
+        self.history_store.flush();
+        if let Err(e) = self.sessions.save(LAST_SESSION, &self.session()) {
+            error!("Failed to save the session: {:#}", e);
+        }
+        self.sessions.remove(AUTOSAVE_SESSION);
+    }
+
+    /// A snapshot of the open windows and tabs.
+    pub fn session(&self) -> Session {
+        let windows = self
+            .windows
+            .iter()
+            .map(|win| WindowSession {
+                tabs: win.tabs.iter().map(save_tab).collect(),
+                current: win.current,
+            })
+            .collect();
+        Session {
+            windows,
+            active: self.active,
+            mode: self.key_mode.to_string(),
+        }
+    }
+
+    /// Replaces the open windows with those of a session.
+    fn open_session(
+        &mut self,
+        target: &EventLoopWindowTarget<UserEvent>,
+        session: &Session,
+    ) -> anyhow::Result<()> {
+        let windows = session
+            .windows
+            .iter()
+            .filter(|saved| !saved.tabs.is_empty())
+            .map(|saved| self.build_window(target, saved))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        // Dropping the old windows closes them.
+        self.windows = windows;
+        self.active = session.active.min(self.windows.len() - 1);
+        let _ = self.webview().focus();
+        // Overlay modes have nothing to return to.
+        let mode = match session.mode.parse() {
+            Ok(KeyMode::Insert) => KeyMode::Insert,
+            _ => KeyMode::Normal,
+        };
+        self.set_key_mode(mode);
+        Ok(())
+    }
+
+    pub fn session_save(&self, name: &str) {
+        let name = if name.is_empty() {
+            DEFAULT_SESSION
+        } else {
+            name
+        };
+        match self.sessions.save(name, &self.session()) {
+            Ok(()) => self.echo(&format!("Session saved: {name}")),
+            Err(e) => self.echo_error(&format!("Failed to save session {name}: {e:#}")),
+        }
+    }
+
+    pub fn session_load(&mut self, target: &EventLoopWindowTarget<UserEvent>, name: &str) {
+        let name = if name.is_empty() {
+            DEFAULT_SESSION
+        } else {
+            name
+        };
+        let result = self
+            .sessions
+            .load(name)
+            .and_then(|session| self.open_session(target, &session));
+        if let Err(e) = result {
+            self.echo_error(&format!("Failed to load session {name}: {e:#}"));
+        }
+    }
+
+    /// Saves the crash-recovery session and the history, forgetting pages
+    /// that have grown too old.
+    pub fn autosave(&mut self) {
+        if let Err(e) = self.sessions.save(AUTOSAVE_SESSION, &self.session()) {
+            error!("Failed to autosave the session: {:#}", e);
+        }
+        if self.settings.history_days > 0 {
+            self.history_store.prune(self.settings.history_days);
+        }
+        self.history_store.flush();
+        self.last_autosave = Instant::now();
+    }
+
+    /// When the crash-recovery session is due to be saved, unless disabled.
+    pub fn autosave_deadline(&self) -> Option<Instant> {
+        let interval = self.settings.session_autosave;
+        (interval > 0).then(|| self.last_autosave + Duration::from_secs(interval))
     }
 
     pub fn show_help(&self) {
         let map = self.key_mgr.get_help_map(self.key_mode);
-        let json = serde_json::to_string(&map).unwrap();
-        let _ = self
-            .webview
-            .evaluate_script(&format!("window.showHelp({json})"));
+        self.webview()
+            .call_locked(self.ipc.token(), "window.showHelp", (map,));
     }
 
     pub fn show_url(&self) {
-        let url = self.history.current();
-        let _ = self
-            .webview
-            .evaluate_script(&format!(r#"window.showUrlBar("{url}")"#));
+        self.webview().call_locked(
+            self.ipc.token(),
+            "window.showUrlBar",
+            (self.tab().history.current(),),
+        );
     }
 
     pub fn change_url(&mut self, url: &str) {
-        self.set_url(url);
+        let Some(url) = self.checked_url(url) else {
+            return;
+        };
+        self.tab_mut().history.expect_user_navigation();
         debug!("Changing url to {}", url);
-        let script = format!(r#"window.location.href = "{}";"#, url);
-        let _ = self.webview.evaluate_script(&script);
+        self.webview().call("window.location.assign", (url,));
+    }
+
+    /// Resolves `input` like `resolve_url`, unless it is a `javascript:`
+    /// URL, which is refused with an error.
+    fn checked_url(&self, input: &str) -> Option<String> {
+        if input
+            .trim_start()
+            .to_ascii_lowercase()
+            .starts_with("javascript:")
+        {
+            self.echo_error("Refusing to open a javascript: URL");
+            return None;
+        }
+        Some(self.resolve_url(input))
+    }
+
+    /// Resolves URL bar input, which opens the homepage when empty.
+    fn resolve_url(&self, input: &str) -> String {
+        match input.trim() {
+            "" => self.settings.homepage.clone(),
+            input => url::resolve(input, &self.settings),
+        }
     }
 
     pub fn refresh_url(&mut self, hard: bool) {
-        let _ = if hard {
-            self.webview
-                .evaluate_script("window.location.reload(true);")
-        } else {
-            self.webview.evaluate_script("window.location.reload();")
-        };
+        self.webview().call("window.location.reload", (hard,));
     }
 
     pub fn copy_url(&mut self) {
-        let url = self.history.current();
+        let url = self.windows[self.active].tab().history.current();
         let _ = self.clipboard.set_text(url);
     }
 
     pub fn paste_url(&mut self) {
-        let url = self.clipboard.get_text().unwrap();
-        self.set_url(&url);
-        let script = format!(r#"window.location.href = "{}";"#, url);
-        let _ = self.webview.evaluate_script(&script);
+        match self.clipboard.get_text() {
+            Ok(url) => self.change_url(url.trim()),
+            Err(e) => self.echo_error(&format!("Failed to read the clipboard: {e}")),
+        }
     }
 
     pub fn search(&mut self, needle: &str) {
-        let script = format!(r#"window.searchHighlight("{}");"#, needle);
-        let _ = self.webview.evaluate_script(&script);
+        self.remember_position();
+        self.webview()
+            .call_locked(self.ipc.token(), "window.searchHighlight", (needle,));
+    }
+
+    pub fn search_next(&mut self, count: usize) {
+        self.remember_position();
+        self.webview()
+            .call_locked(self.ipc.token(), "window.searchNext", (count,));
     }
 
-    pub fn search_next(&mut self) {
-        let _ = self.webview.evaluate_script("window.searchNext();");
+    pub fn search_prev(&mut self, count: usize) {
+        self.remember_position();
+        self.webview()
+            .call_locked(self.ipc.token(), "window.searchPrev", (count,));
     }
 
-    pub fn search_prev(&mut self) {
-        let _ = self.webview.evaluate_script("window.searchPrev();");
+    /// Lets the user pick a page from the global history, optionally
+    /// narrowed down by a fuzzy `filter`.
+    pub fn show_history(&self, filter: &str) {
+        let entries = if filter.is_empty() {
+            self.history_store.entries()
+        } else {
+            self.history_store.query_fuzzy(filter)
+        };
+        let (items, commands): (Vec<String>, Vec<String>) = entries
+            .iter()
+            .take(MAX_PICKER_ITEMS)
+            .map(|entry| {
+                let item = format!("{:>4}  {}  {}", entry.visit_count, entry.title, entry.url);
+                (item, format!("open {}", command::quote(&entry.url)))
+            })
+            .unzip();
+        self.webview().call_locked(
+            self.ipc.token(),
+            "window.showPicker",
+            ("History", items, 0, commands),
+        );
+    }
+
+    /// Bookmarks the current page, adding the whitespace-separated `tags`.
+    pub fn bookmark_add(&mut self, tags: &str) {
+        let tags: Vec<String> = tags.split_whitespace().map(String::from).collect();
+        let tab = self.windows[self.active].tab();
+        let url = tab.history.current();
+        if self.bookmarks.add(url, &tab.title, &tags) {
+            self.echo(&format!("Bookmarked {url}"));
+        } else {
+            self.echo(&format!("Updated the bookmark of {url}"));
+        }
+    }
+
+    /// Removes the bookmark of `url`, or of the current page when empty.
+    pub fn bookmark_remove(&mut self, url: &str) {
+        let url = match url.trim() {
+            "" => self.tab().history.current().to_string(),
+            url => url.to_string(),
+        };
+        if self.bookmarks.remove(&url) {
+            self.echo(&format!("Removed the bookmark of {url}"));
+        } else {
+            self.echo_error(&format!("Not bookmarked: {url}"));
+        }
+    }
+
+    pub fn show_bookmarks(&self, filter: &str) {
+        let (items, commands): (Vec<String>, Vec<String>) = self
+            .bookmarks
+            .query(filter)
+            .iter()
+            .take(MAX_PICKER_ITEMS)
+            .map(|b| {
+                let tags: String = b.tags.iter().map(|tag| format!("  #{tag}")).collect();
+                let item = format!("{}  {}{}", b.title, b.url, tags);
+                (item, format!("open {}", command::quote(&b.url)))
+            })
+            .unzip();
+        self.webview().call_locked(
+            self.ipc.token(),
+            "window.showPicker",
+            ("Bookmarks", items, 0, commands),
+        );
+    }
+
+    pub fn bookmarks_import(&mut self, path: &str) {
+        match std::fs::read_to_string(config::expand_path(path)) {
+            Ok(html) => {
+                let added = self.bookmarks.import(&html);
+                self.echo(&format!("Imported {added} bookmarks"));
+            }
+            Err(e) => self.echo_error(&format!("Failed to read {path}: {e}")),
+        }
+    }
+
+    pub fn bookmarks_export(&self, path: &str) {
+        match std::fs::write(config::expand_path(path), self.bookmarks.export()) {
+            Ok(()) => self.echo(&format!("Exported the bookmarks to {path}")),
+            Err(e) => self.echo_error(&format!("Failed to write {path}: {e}")),
+        }
+    }
+
+    pub fn quickmark_set(&mut self, name: &str) {
+        match BookmarkStore::parse_quickmark(name) {
+            Ok(name) => {
+                let url = self.windows[self.active].tab().history.current();
+                self.bookmarks.set_quickmark(name, url);
+                self.echo(&format!("Quickmark {name} set to {url}"));
+            }
+            Err(msg) => self.echo_error(&msg),
+        }
+    }
+
+    pub fn quickmark_open(&mut self, name: &str) {
+        let url = BookmarkStore::parse_quickmark(name).and_then(|name| {
+            self.bookmarks
+                .quickmark(name)
+                .map(String::from)
+                .ok_or_else(|| format!("Quickmark not set: {name}"))
+        });
+        match url {
+            Ok(url) => self.change_url(&url),
+            Err(msg) => self.echo_error(&msg),
+        }
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history_store.clear();
+        self.echo("History cleared");
+    }
+
+    pub fn run_command(&mut self, line: &str) -> Result<(Action, usize), String> {
+        self.cmd_history.push(line);
+        command::parse(line).inspect_err(|msg| {
+            error!("Invalid command '{}': {}", line, msg);
+            self.echo_error(msg);
+        })
+    }
+
+    pub fn send_command_history(&self) {
+        self.webview().call_locked(
+            self.ipc.token(),
+            "window.setCommandHistory",
+            (self.cmd_history.entries(),),
+        );
+    }
+
+    pub fn complete(&self, line: &str) {
+        let completion = cmdline::complete(line, |word| {
+            self.history_store
+                .query_prefix(word)
+                .into_iter()
+                .map(|entry| entry.url.clone())
+                .collect()
+        });
+        self.webview()
+            .call_locked(self.ipc.token(), "window.showCompletions", (completion,));
+    }
+
+    /// Suggests pages for URL bar input from the open tabs, the bookmarks
+    /// and the history.
+    pub fn url_complete(&mut self, input: &str) {
+        let now = history::now();
+        let page = |kind, url: &str, title: &str, frecency| Suggestion {
+            kind,
+            value: url.to_string(),
+            title: title.to_string(),
+            command: None,
+            frecency,
+        };
+        let win = self.win();
+        let tabs = win
+            .tabs
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != win.current)
+            .map(|(i, tab)| Suggestion {
+                command: Some(format!("buffer {}", i + 1)),
+                ..page(SuggestionKind::Tab, tab.history.current(), tab.title(), 0)
+            });
+        let bookmarks = self
+            .bookmarks
+            .query("")
+            .into_iter()
+            .map(|b| page(SuggestionKind::Bookmark, &b.url, &b.title, 0));
+        let history = self.history_store.entries().into_iter().map(|entry| {
+            let frecency = entry.frecency(now);
+            page(SuggestionKind::History, &entry.url, &entry.title, frecency)
+        });
+        let candidates = tabs.chain(bookmarks).chain(history).collect();
+        let suggestions = url::suggest(input, candidates, &self.settings, MAX_URL_SUGGESTIONS);
+        self.webview().call_locked(
+            self.ipc.token(),
+            "window.showUrlSuggestions",
+            (input, &suggestions),
+        );
+        self.url_suggestions = suggestions;
+    }
+
+    /// The action for the URL bar suggestion at `index`, or for opening
+    /// `value` if the suggestions have changed since it was shown.
+    pub fn url_select(&self, index: usize, value: &str) -> (Action, usize) {
+        let selected = self
+            .url_suggestions
+            .get(index)
+            .filter(|suggestion| suggestion.value == value);
+        match selected.and_then(|suggestion| suggestion.command.as_deref()) {
+            Some(line) => command::parse(line).unwrap_or_else(|msg| {
+                error!("Invalid suggestion command '{}': {}", line, msg);
+                (Action::NormalMode, 1)
+            }),
+            None => (Action::ChangeURL(value.to_string()), 1),
+        }
+    }
+
+    /// Sets the cookie rule of `pattern`, or of the host of the current page
+    /// and its subdomains when empty.
+    pub fn cookies_rule(&mut self, pattern: &str, rule: CookieRule) {
+        let pattern = match pattern.trim() {
+            "" => match cookie::host(self.tab().history.current()) {
+                Some(host) => format!("*.{host}"),
+                None => return self.echo_error("The current page has no domain"),
+            },
+            pattern => pattern.to_string(),
+        };
+        match self.cookie_mgr.set_rule(&pattern, rule) {
+            Ok(pattern) => {
+                if let Err(e) = self.cookie_mgr.enforce(self.webview()) {
+                    error!("Failed to enforce the cookie rules: {}", e);
+                }
+                self.echo(&format!("Cookies of {pattern}: {rule}"));
+            }
+            Err(msg) => self.echo_error(&msg),
+        }
+    }
+
+    /// Lists the cookies, grouped by domain, of the domains containing
+    /// `filter`. `d` deletes the selected domain or cookie.
+    pub fn show_cookies(&self, filter: &str) {
+        let groups = match self.cookie_mgr.cookies(self.webview()) {
+            Ok(groups) => groups,
+            Err(e) => return self.echo_error(&format!("Failed to read the cookies: {e}")),
+        };
+        let now = OffsetDateTime::now_utc();
+        let mut items = Vec::new();
+        let mut deletions = Vec::new();
+        for (domain, cookies) in groups.iter().filter(|(domain, _)| domain.contains(filter)) {
+            items.push(format!("{}  ({})", domain, cookies.len()));
+            deletions.push(format!("cookies-clear {}", command::quote(domain)));
+            for cookie in cookies {
+                items.push(format!("    {}", cookie::describe(cookie, now)));
+                deletions.push(format!(
+                    "cookie-delete {} {} {}",
+                    command::quote(domain),
+                    command::quote(cookie.name()),
+                    command::quote(cookie.path().unwrap_or("/"))
+                ));
+            }
+        }
+        items.truncate(MAX_PICKER_ITEMS);
+        deletions.truncate(MAX_PICKER_ITEMS);
+        let commands: [String; 0] = [];
+        self.webview().call_locked(
+            self.ipc.token(),
+            "window.showPicker",
+            ("Cookies", items, 0, commands, deletions),
+        );
+    }
+
+    pub fn cookie_delete(&self, domain: &str, name: &str, path: &str) {
+        match self.cookie_mgr.delete(self.webview(), domain, name, path) {
+            Ok(0) => self.echo_error(&format!("No cookie {name} on {domain}")),
+            Ok(_) => self.echo(&format!("Deleted cookie {name} of {domain}")),
+            Err(e) => self.echo_error(&format!("Failed to delete cookie {name}: {e}")),
+        }
+    }
+
+    /// Deletes the cookies of `domain`, or of the host of the current page
+    /// when empty, including those of subdomains.
+    pub fn cookies_clear(&self, domain: &str) {
+        let domain = match domain.trim() {
+            "" => match cookie::host(self.tab().history.current()) {
+                Some(host) => host,
+                None => return self.echo_error("The current page has no domain"),
+            },
+            domain => domain.to_string(),
+        };
+        match self.cookie_mgr.clear(self.webview(), Some(&domain)) {
+            Ok(deleted) => self.echo(&format!("Deleted {deleted} cookies of {domain}")),
+            Err(e) => self.echo_error(&format!("Failed to delete cookies: {e}")),
+        }
+    }
+
+    pub fn cookies_clear_all(&self) {
+        match self.cookie_mgr.clear(self.webview(), None) {
+            Ok(deleted) => self.echo(&format!("Deleted {deleted} cookies")),
+            Err(e) => self.echo_error(&format!("Failed to delete cookies: {e}")),
+        }
+    }
+
+    pub fn echo(&self, msg: &str) {
+        self.webview()
+            .call_locked(self.ipc.token(), "window.showMessage", (msg, false));
+    }
+
+    pub fn echo_error(&self, msg: &str) {
+        self.webview()
+            .call_locked(self.ipc.token(), "window.showMessage", (msg, true));
+    }
+
+    pub fn set_option(&mut self, key: &str, value: &str) {
+        match self.settings.set(key, value) {
+            Ok(()) => {
+                match key {
+                    "cookie-policy" => self.cookie_mgr.set_policy(self.settings.cookie_policy),
+                    "block-third-party-cookies" => self
+                        .cookie_mgr
+                        .set_block_third_party(self.settings.block_third_party_cookies),
+                    _ => {}
+                }
+                self.echo(&format!("{key}={value}"));
+            }
+            Err(msg) => self.echo_error(&msg),
+        }
+    }
+
+    pub fn bind(&mut self, mode: KeyMode, keys: &str, action: &str) {
+        match self.key_mgr.bind(mode, keys, action) {
+            Ok(()) => self.reload_bindings(),
+            Err(msg) => self.echo_error(&msg),
+        }
+    }
+
+    pub fn unbind(&mut self, mode: KeyMode, keys: &str) {
+        match self.key_mgr.unbind(mode, keys) {
+            Ok(()) => self.reload_bindings(),
+            Err(msg) => self.echo_error(&msg),
+        }
+    }
+
+    pub fn reload_bindings(&self) {
+        for tab in self.windows.iter().flat_map(|win| &win.tabs) {
+            self.reload_bindings_in(&tab.webview);
+        }
+    }
+
+    /// Replaces the bindings a page was created with if they have changed.
+    fn reload_bindings_in(&self, webview: &WebView) {
+        if self.key_mgr.is_dirty() {
+            webview.call_locked(
+                self.ipc.token(),
+                "window.setKeyTries",
+                (self.key_mgr.export_tries(),),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_count() {
+        assert_eq!(split_count("scroll-down"), Some((1, "scroll-down")));
+        assert_eq!(split_count("12scroll-down"), Some((12, "scroll-down")));
+        assert_eq!(split_count("0tab-next"), Some((1, "tab-next")));
+        assert_eq!(split_count("5 command:open x"), None);
+        assert_eq!(split_count("1:exit"), None);
+        assert_eq!(
+            split_count("10000scroll-down"),
+            Some((MAX_COUNT, "scroll-down"))
+        );
+        assert_eq!(
+            split_count("99999999999999999999999scroll-down"),
+            Some((MAX_COUNT, "scroll-down"))
+        );
+        assert_eq!(split_count("12"), None);
     }
 }
 
@@ -20,8 +20,10 @@
 */
 
 const STATUSBAR_JS: &str = r#"
+let statusBar = null;
+
 const initStatusBar = () => {
-  const statusBar = document.createElement("div");
+  statusBar = document.createElement("div");
   statusBar.id = "peyvand-statusbar";
   Object.assign(statusBar.style, {
     position: "fixed",
@@ -39,11 +41,15 @@ const initStatusBar = () => {
   });
   statusBar.innerText = "Normal";
   document.body.appendChild(statusBar);
+};
 
-  window.updateStatus = (text) => {
-    statusBar.innerText = text;
-  };
+const showMessage = (text, isError) => {
+  if (!statusBar) return;
+  statusBar.innerText = text;
+  statusBar.style.color = isError ? '#ff6b6b' : "white";
 };
+const updateStatus = (text) => showMessage(text, false);
+exposeEntryPoint("showMessage", showMessage);
 
 if (document.readyState === "loading") {
   document.addEventListener("DOMContentLoaded", initStatusBar);
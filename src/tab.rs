@@ -0,0 +1,146 @@
+/* tab.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::{hint::HintAction, history::History};
+use wry::WebView;
+
+const TABBAR_JS: &str = r#"
+let tabBar = null;
+exposeEntryPoint("showTabs", (titles, current) => {
+  if (tabBar) tabBar.remove();
+  tabBar = null;
+  if (titles.length < 2) return;
+
+  const bar = document.createElement("div");
+  Object.assign(bar.style, {
+    position: "fixed",
+    top: "0px",
+    left: "0px",
+    width: "100%",
+    display: "flex",
+    backgroundColor: "rgba(0,0,0,0.85)",
+    color: "white",
+    fontFamily: "monospace",
+    fontSize: "12px",
+    lineHeight: "20px",
+    zIndex: "999999",
+  });
+  titles.forEach((title, i) => {
+    const tab = document.createElement("div");
+    tab.textContent = (i + 1) + ": " + title;
+    Object.assign(tab.style, {
+      flex: "1 1 0",
+      minWidth: "0",
+      overflow: "hidden",
+      whiteSpace: "nowrap",
+      textOverflow: "ellipsis",
+      padding: "0 6px",
+      backgroundColor: i === current ? '#444' : "transparent",
+    });
+    bar.appendChild(tab);
+  });
+  tabBar = mountOverlay(bar);
+});
+"#;
+
+pub struct TabBar {}
+
+impl TabBar {
+    pub fn get_js() -> &'static str {
+        TABBAR_JS
+    }
+}
+
+/// Identifies a tab for the lifetime of the process, independently of its
+/// position in the tab list.
+pub type TabId = usize;
+
+pub struct Tab {
+    pub id: TabId,
+    pub webview: WebView,
+    pub history: History,
+    pub title: String,
+    /// Scroll offset of the page, as last reported by it.
+    pub scroll: (f64, f64),
+    /// Scroll offset to restore once the page has loaded.
+    pub pending_scroll: Option<(f64, f64)>,
+    /// The action of the hints shown in the page, which the page may send
+    /// back a `hinted:` message for.
+    pub pending_hint: Option<HintAction>,
+}
+
+impl Tab {
+    /// The document title, or the URL while the page has none.
+    pub fn title(&self) -> &str {
+        if self.title.is_empty() {
+            self.history.current()
+        } else {
+            &self.title
+        }
+    }
+}
+
+/// The index `steps` tabs away from `current`, wrapping around both ends.
+pub fn cycle(current: usize, len: usize, steps: isize) -> usize {
+    (current as isize + steps).rem_euclid(len as isize) as usize
+}
+
+/// Resolves a `:tabmove` argument to the new index of the current tab:
+/// an absolute position counted from 0, `+N`/`-N` relative to the current
+/// tab, or the last position when empty.
+pub fn move_target(current: usize, len: usize, arg: &str) -> Result<usize, String> {
+    let invalid = || format!("Invalid tab position: {arg}");
+    let target = match arg.trim() {
+        "" => return Ok(len - 1),
+        arg if arg.starts_with(['+', '-']) => {
+            current as isize + arg.parse::<isize>().map_err(|_| invalid())?
+        }
+        arg => arg.parse::<isize>().map_err(|_| invalid())?,
+    };
+    Ok(target.clamp(0, len as isize - 1) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycle() {
+        assert_eq!(cycle(0, 3, 1), 1);
+        assert_eq!(cycle(2, 3, 1), 0);
+        assert_eq!(cycle(0, 3, -1), 2);
+        assert_eq!(cycle(1, 3, 5), 0);
+        assert_eq!(cycle(0, 1, -4), 0);
+    }
+
+    #[test]
+    fn test_move_target() {
+        assert_eq!(move_target(1, 4, ""), Ok(3));
+        assert_eq!(move_target(1, 4, "0"), Ok(0));
+        assert_eq!(move_target(1, 4, "+1"), Ok(2));
+        assert_eq!(move_target(1, 4, "-1"), Ok(0));
+        assert_eq!(move_target(1, 4, "-5"), Ok(0));
+        assert_eq!(move_target(1, 4, "9"), Ok(3));
+        assert!(move_target(1, 4, "left").is_err());
+    }
+}
+
+/* tab.rs ends here */
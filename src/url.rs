@@ -19,13 +19,34 @@
 * along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
+use crate::{
+    config::{self, Settings},
+    fuzzy,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Search engines available without configuration. `{}` stands for the
+/// encoded query.
+pub const SEARCH_ENGINES: &[(&str, &str)] = &[
+    ("ddg", "https://duckduckgo.com/?q={}"),
+    ("google", "https://www.google.com/search?q={}"),
+    ("gh", "https://github.com/search?q={}"),
+    ("wiki", "https://en.wikipedia.org/w/index.php?search={}"),
+    ("crates", "https://crates.io/search?q={}"),
+];
+
+/// Schemes whose URLs have no `//` after the colon.
+const OPAQUE_SCHEMES: &[&str] = &["about", "data", "mailto", "file", "view-source"];
+
 const URL_JS: &str = r#"
-window.showUrlBar = function(initialUrl = "") {
-    if (window.overlays.urlBar) window.overlays.urlBar.remove();
+let urlBar = null;
+
+exposeEntryPoint("showUrlBar", (initialUrl = "") => {
+    if (urlBar) urlBar.remove();
 
     console.log("Entered input mode");
     const input = document.createElement("input");
-    input.id = "url-bar-overlay";
     input.type = "text";
     input.value = initialUrl;
     input.style.position = "fixed";
@@ -41,21 +62,111 @@ window.showUrlBar = function(initialUrl = "") {
     input.style.border = "1px solid #ccc";
     input.style.outline = "none";
 
-    document.body.appendChild(input);
+    const menu = document.createElement("div");
+    Object.assign(menu.style, {
+        position: "fixed",
+        left: "10%",
+        width: "80%",
+        maxHeight: "50%",
+        overflowY: "auto",
+        backgroundColor: "rgba(0,0,0,0.9)",
+        color: "white",
+        fontFamily: "monospace",
+        fontSize: "14px",
+        zIndex: "10000",
+    });
+
+    const box = document.createElement("div");
+    box.append(input, menu);
+    const host = mountOverlay(box);
+    menu.style.top = input.getBoundingClientRect().bottom + "px";
     input.focus();
-    window.overlays.urlBar = input;
 
-    function remove() { input.remove(); window.overlays.urlBar = null; document.removeEventListener("keydown", escHandler); }
-    function escHandler(e) { if (e.key === "Escape") remove(); }
+    let timer = null;
+    const bar = {
+        input,
+        menu,
+        // The input the shown suggestions were computed for.
+        query: null,
+        suggestions: [],
+        index: -1,
+        remove() {
+            clearTimeout(timer);
+            host.remove();
+            urlBar = null;
+            document.removeEventListener("keydown", escHandler);
+        },
+    };
+    urlBar = bar;
+
+    function escHandler(e) { if (e.key === "Escape") bar.remove(); }
+
+    input.addEventListener("input", () => {
+        clearTimeout(timer);
+        timer = setTimeout(() => {
+            bar.query = input.value;
+            sendAction("url-complete:" + input.value);
+        }, 80);
+    });
 
     input.addEventListener("keydown", (e) => {
-        if (e.key === "Enter") {
-            window.ipc.postMessage("change-url:" + input.value);
-            remove();
-        } else if (e.key === "Escape") remove();
+        if (!e.isTrusted) return;
+        const next = (e.key === "Tab" && !e.shiftKey) || e.key === "ArrowDown" || (e.ctrlKey && e.key === "n");
+        const prev = (e.key === "Tab" && e.shiftKey) || e.key === "ArrowUp" || (e.ctrlKey && e.key === "p");
+        if (next || prev) {
+            selectUrlSuggestion(next ? 1 : -1);
+            e.preventDefault();
+        } else if (e.key === "Enter") {
+            const selected = bar.suggestions[bar.index];
+            if (selected && input.value === selected.value) {
+                sendAction("url-select:" + bar.index + ":" + selected.value);
+            } else {
+                sendAction("change-url:" + input.value);
+            }
+            bar.remove();
+        } else if (e.key === "Escape") bar.remove();
     });
 
     document.addEventListener("keydown", escHandler);
+});
+
+exposeEntryPoint("showUrlSuggestions", (query, suggestions) => {
+    const bar = urlBar;
+    if (!bar || bar.query !== query) return;
+    bar.suggestions = suggestions;
+    bar.index = -1;
+    bar.menu.replaceChildren(...suggestions.map((suggestion) => {
+        const row = document.createElement("div");
+        Object.assign(row.style, {
+            padding: "2px 6px",
+            whiteSpace: "nowrap",
+            overflow: "hidden",
+            textOverflow: "ellipsis",
+        });
+        const kind = document.createElement("span");
+        kind.textContent = suggestion.kind.padEnd(10);
+        kind.style.whiteSpace = "pre";
+        kind.style.color = '#888';
+        const title = document.createElement("span");
+        title.textContent = suggestion.title + "  ";
+        const url = document.createElement("span");
+        url.textContent = suggestion.value;
+        url.style.color = '#8cf';
+        row.append(kind, title, url);
+        return row;
+    }));
+});
+
+const selectUrlSuggestion = (step) => {
+    const bar = urlBar;
+    if (!bar || !bar.suggestions.length) return;
+    const n = bar.suggestions.length;
+    bar.index = bar.index < 0 && step < 0 ? n - 1 : (bar.index + step + n) % n;
+    Array.from(bar.menu.children).forEach((row, i) => {
+        row.style.backgroundColor = i === bar.index ? '#444' : "transparent";
+    });
+    bar.menu.children[bar.index].scrollIntoView({ block: "nearest" });
+    bar.input.value = bar.suggestions[bar.index].value;
 };
 "#;
 
@@ -69,4 +180,428 @@ impl Url {
         Self {}
     }
 }
+
+/// The URL template of a search engine, preferring the configured ones.
+pub fn search_engine<'a>(name: &str, custom: &'a BTreeMap<String, String>) -> Option<&'a str> {
+    custom.get(name).map(String::as_str).or_else(|| {
+        SEARCH_ENGINES
+            .iter()
+            .find(|(engine, _)| *engine == name)
+            .map(|(_, template)| *template)
+    })
+}
+
+/// Turns what was typed into the URL bar into a URL: URLs are kept, hosts get
+/// a scheme, paths become `file://` URLs, and anything else is searched for,
+/// with the engine named by the first word or the default one.
+pub fn resolve(input: &str, settings: &Settings) -> String {
+    let input = input.trim();
+    if let Some((keyword, query)) = input.split_once(char::is_whitespace) {
+        if let Some(template) = search_engine(keyword, &settings.search_engines) {
+            return search(template, query.trim());
+        }
+    }
+    if has_scheme(input) {
+        return input.to_string();
+    }
+    if input.starts_with(['/', '~']) || input.starts_with("./") || input.starts_with("../") {
+        let path = config::expand_path(input);
+        let path = std::env::current_dir()
+            .map(|dir| dir.join(&path))
+            .unwrap_or(path);
+        return format!("file://{}", encode(&path.to_string_lossy(), "/"));
+    }
+    if let Some(scheme) = host_scheme(input) {
+        return format!("{scheme}://{input}");
+    }
+    let template = search_engine(&settings.search_engine, &settings.search_engines)
+        .unwrap_or(SEARCH_ENGINES[0].1);
+    search(template, input)
+}
+
+fn search(template: &str, query: &str) -> String {
+    template.replace("{}", &encode(query, ""))
+}
+
+/// Whether `url` is an `http` or `https` URL.
+pub fn is_web(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https")
+    })
+}
+
+fn has_scheme(input: &str) -> bool {
+    let Some((scheme, rest)) = input.split_once(':') else {
+        return false;
+    };
+    let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid
+        && (rest.starts_with("//")
+            || OPAQUE_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()))
+}
+
+/// The scheme to load `input` with if it starts with a host name, such as
+/// `example.com/docs` or `localhost:8080`. Local hosts rarely serve HTTPS.
+fn host_scheme(input: &str) -> Option<&'static str> {
+    if input.contains(char::is_whitespace) {
+        return None;
+    }
+    let authority = input.split(['/', '?', '#']).next().unwrap_or_default();
+    if let Some(ipv6) = authority.strip_prefix('[') {
+        return ipv6.contains(']').then_some("http");
+    }
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => host,
+        Some(_) => return None,
+        None => authority,
+    };
+    let labels: Vec<&str> = host.split('.').collect();
+    let is_ipv4 = labels.len() == 4 && labels.iter().all(|label| label.parse::<u8>().is_ok());
+    if host.eq_ignore_ascii_case("localhost") || is_ipv4 {
+        return Some("http");
+    }
+    let tld = labels.last().unwrap_or(&"");
+    let is_domain = labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        })
+        && (tld.chars().count() >= 2 && tld.chars().all(char::is_alphabetic)
+            || tld.starts_with("xn--"));
+    is_domain.then_some("https")
+}
+
+/// Where a URL bar suggestion comes from, in order of precedence when the
+/// same URL is found in several places.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SuggestionKind {
+    Search,
+    Tab,
+    Bookmark,
+    History,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Suggestion {
+    pub kind: SuggestionKind,
+    /// What the URL bar is filled with when the suggestion is selected.
+    pub value: String,
+    pub title: String,
+    /// A command to run instead of opening `value`, such as switching to an
+    /// open tab. It stays in peyvand, which the URL bar names the picked
+    /// suggestion to by index.
+    #[serde(skip)]
+    pub command: Option<String>,
+    #[serde(skip)]
+    pub frecency: u64,
+}
+
+/// Ranks the pages in `candidates` that fuzzily match `input` by how well
+/// they match, how often and recently they were visited and where they come
+/// from, after the search engines whose keyword `input` starts with.
+pub fn suggest(
+    input: &str,
+    candidates: Vec<Suggestion>,
+    settings: &Settings,
+    limit: usize,
+) -> Vec<Suggestion> {
+    let mut suggestions = engine_suggestions(input.trim_start(), settings);
+
+    // Keep one suggestion per URL, from the best source but with the
+    // frecency of the page.
+    let mut pages: HashMap<String, Suggestion> = HashMap::new();
+    for candidate in candidates {
+        match pages.get_mut(&candidate.value) {
+            Some(page) => {
+                let frecency = page.frecency.max(candidate.frecency);
+                if candidate.kind < page.kind {
+                    *page = candidate;
+                }
+                page.frecency = frecency;
+            }
+            None => {
+                pages.insert(candidate.value.clone(), candidate);
+            }
+        }
+    }
+
+    let pattern = input.trim();
+    let mut ranked: Vec<_> = pages
+        .into_values()
+        .filter_map(|page| {
+            let url = page
+                .value
+                .split_once("://")
+                .map_or(&*page.value, |(_, rest)| rest);
+            let score = fuzzy::score(pattern, url).max(fuzzy::score(pattern, &page.title))?;
+            let bonus = match page.kind {
+                SuggestionKind::Tab => 2000,
+                SuggestionKind::Bookmark => 1000,
+                _ => 0,
+            };
+            let rank = score + bonus + 250 * i64::from((page.frecency + 1).ilog2());
+            Some((rank, page))
+        })
+        .collect();
+    ranked.sort_by(|(a_rank, a), (b_rank, b)| b_rank.cmp(a_rank).then(a.value.cmp(&b.value)));
+    suggestions.extend(ranked.into_iter().map(|(_, page)| page));
+    suggestions.truncate(limit);
+    suggestions
+}
+
+/// Search engines whose keyword starts with what was typed, or the one
+/// whose keyword it already starts with.
+fn engine_suggestions(input: &str, settings: &Settings) -> Vec<Suggestion> {
+    let suggestion = |value: String, title: String| Suggestion {
+        kind: SuggestionKind::Search,
+        value,
+        title,
+        command: None,
+        frecency: 0,
+    };
+    if let Some((keyword, query)) = input.split_once(char::is_whitespace) {
+        let query = query.trim();
+        return match search_engine(keyword, &settings.search_engines) {
+            Some(_) if !query.is_empty() => vec![suggestion(
+                input.to_string(),
+                format!("Search {keyword} for {query}"),
+            )],
+            _ => Vec::new(),
+        };
+    }
+    if input.is_empty() {
+        return Vec::new();
+    }
+    let builtin = SEARCH_ENGINES.iter().map(|(name, _)| *name);
+    let mut names: Vec<&str> = settings
+        .search_engines
+        .keys()
+        .map(String::as_str)
+        .chain(builtin)
+        .filter(|name| name.starts_with(input))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .map(|name| suggestion(format!("{name} "), format!("Search with {name}")))
+        .collect()
+}
+
+/// Percent-encodes everything but unreserved characters and `keep`.
+fn encode(text: &str, keep: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric()
+            || matches!(byte, b'-' | b'.' | b'_' | b'~')
+            || keep.as_bytes().contains(&byte)
+        {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(input: &str) -> String {
+        resolve(input, &Settings::default())
+    }
+
+    #[test]
+    fn test_urls_are_kept() {
+        for url in [
+            "https://example.com/a?b=c",
+            "http://localhost:8080",
+            "about:blank",
+            "file:///etc/hosts",
+            "data:text/html,hi",
+        ] {
+            assert_eq!(resolved(url), url);
+        }
+        assert_eq!(resolved("  https://example.com  "), "https://example.com");
+    }
+
+    #[test]
+    fn test_is_web() {
+        assert!(is_web("https://example.com/"));
+        assert!(is_web("HTTP://example.com/"));
+        assert!(!is_web("file:///etc/passwd"));
+        assert!(!is_web("javascript:alert(1)"));
+        assert!(!is_web("example.com"));
+    }
+
+    #[test]
+    fn test_hosts_get_a_scheme() {
+        assert_eq!(resolved("example.com"), "https://example.com");
+        assert_eq!(
+            resolved("docs.rs/wry/latest?x=1#y"),
+            "https://docs.rs/wry/latest?x=1#y"
+        );
+        assert_eq!(
+            resolved("xn--mgbh0fb.xn--kgbechtv"),
+            "https://xn--mgbh0fb.xn--kgbechtv"
+        );
+        assert_eq!(resolved("پیوند.ایران"), "https://پیوند.ایران");
+        assert_eq!(resolved("localhost:3000/api"), "http://localhost:3000/api");
+        assert_eq!(resolved("192.168.1.1"), "http://192.168.1.1");
+        assert_eq!(resolved("[::1]:8080"), "http://[::1]:8080");
+    }
+
+    #[test]
+    fn test_paths_become_file_urls() {
+        assert_eq!(resolved("/tmp/my file.html"), "file:///tmp/my%20file.html");
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            resolved("./index.html"),
+            format!(
+                "file://{}",
+                encode(&cwd.join("./index.html").to_string_lossy(), "/")
+            )
+        );
+    }
+
+    #[test]
+    fn test_searches() {
+        assert_eq!(
+            resolved("rust lifetimes"),
+            "https://duckduckgo.com/?q=rust%20lifetimes"
+        );
+        assert_eq!(resolved("peyvand"), "https://duckduckgo.com/?q=peyvand");
+        assert_eq!(resolved("1.5"), "https://duckduckgo.com/?q=1.5");
+        assert_eq!(
+            resolved("what is example.com"),
+            "https://duckduckgo.com/?q=what%20is%20example.com"
+        );
+        assert_eq!(
+            resolved("c++ & rust?"),
+            "https://duckduckgo.com/?q=c%2B%2B%20%26%20rust%3F"
+        );
+        assert_eq!(
+            resolved("gh peyvand"),
+            "https://github.com/search?q=peyvand"
+        );
+        assert_eq!(resolved("ddg  a  b "), "https://duckduckgo.com/?q=a%20%20b");
+        assert_eq!(
+            resolved("localhost:x"),
+            "https://duckduckgo.com/?q=localhost%3Ax"
+        );
+    }
+
+    fn page(kind: SuggestionKind, url: &str, title: &str, frecency: u64) -> Suggestion {
+        Suggestion {
+            kind,
+            value: url.to_string(),
+            title: title.to_string(),
+            command: None,
+            frecency,
+        }
+    }
+
+    fn values(suggestions: &[Suggestion]) -> Vec<&str> {
+        suggestions.iter().map(|s| s.value.as_str()).collect()
+    }
+
+    #[test]
+    fn test_suggestions_are_ranked() {
+        use SuggestionKind::*;
+        let candidates = vec![
+            page(History, "https://rust-lang.org/", "Rust", 10),
+            page(
+                History,
+                "https://www.rust-lang.org/learn",
+                "Learn Rust",
+                900,
+            ),
+            page(History, "https://example.com/", "Example", 1000),
+            page(History, "https://docs.rs/", "Docs.rs", 50),
+            page(Bookmark, "https://docs.rs/", "Docs", 0),
+        ];
+        let suggestions = suggest("rust", candidates.clone(), &Settings::default(), 10);
+        // Frecency decides between similar matches.
+        assert_eq!(
+            values(&suggestions),
+            vec!["https://www.rust-lang.org/learn", "https://rust-lang.org/"]
+        );
+
+        let suggestions = suggest("docs", candidates.clone(), &Settings::default(), 10);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].kind, Bookmark);
+        assert_eq!(suggestions[0].frecency, 50);
+
+        // Everything matches an empty input, bookmarks and the most frecent
+        // pages first.
+        let suggestions = suggest("", candidates, &Settings::default(), 3);
+        assert_eq!(
+            values(&suggestions),
+            vec![
+                "https://docs.rs/",
+                "https://example.com/",
+                "https://www.rust-lang.org/learn"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_open_tabs_come_first() {
+        use SuggestionKind::*;
+        let mut tab = page(Tab, "https://a.org/tab", "A tab", 0);
+        tab.command = Some("buffer 2".to_string());
+        let candidates = vec![page(History, "https://a.org/", "A", 100), tab.clone()];
+        let suggestions = suggest("a.org", candidates, &Settings::default(), 10);
+        assert_eq!(suggestions[0], tab);
+    }
+
+    #[test]
+    fn test_engine_suggestions() {
+        let settings = Settings::default();
+        let suggestions = suggest("g", Vec::new(), &settings, 10);
+        assert_eq!(values(&suggestions), vec!["gh ", "google "]);
+        assert!(suggestions.iter().all(|s| s.kind == SuggestionKind::Search));
+
+        let suggestions = suggest("gh peyvand", Vec::new(), &settings, 10);
+        assert_eq!(values(&suggestions), vec!["gh peyvand"]);
+        assert_eq!(suggestions[0].title, "Search gh for peyvand");
+
+        assert!(suggest("gh ", Vec::new(), &settings, 10).is_empty());
+        assert!(suggest("rust lifetimes", Vec::new(), &settings, 10).is_empty());
+    }
+
+    #[test]
+    fn test_configured_engines() {
+        let engines = [
+            ("docs", "https://docs.rs/releases/search?query={}"),
+            ("gh", "https://gitlab.com/search?search={}"),
+        ];
+        let settings = Settings {
+            search_engine: "docs".to_string(),
+            search_engines: engines
+                .iter()
+                .map(|(name, template)| (name.to_string(), template.to_string()))
+                .collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            resolve("serde json", &settings),
+            "https://docs.rs/releases/search?query=serde%20json"
+        );
+        assert_eq!(
+            resolve("gh peyvand", &settings),
+            "https://gitlab.com/search?search=peyvand"
+        );
+        assert_eq!(
+            resolve("wiki Surf", &settings),
+            "https://en.wikipedia.org/w/index.php?search=Surf"
+        );
+    }
+}
+
 /* url.rs ends here */
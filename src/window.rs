@@ -0,0 +1,116 @@
+/* window.rs
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::{
+    ipc::Ipc,
+    script::Eval,
+    tab::{Tab, TabId},
+};
+use tao::window::{Window, WindowId};
+use wry::{dpi::LogicalPosition, Rect, WebView};
+
+/// A top-level window and the tabs shown in it.
+pub struct BrowserWindow {
+    pub window: Window,
+    pub tabs: Vec<Tab>,
+    pub current: usize,
+    ipc: Ipc,
+}
+
+impl BrowserWindow {
+    pub fn new(window: Window, ipc: Ipc) -> Self {
+        Self {
+            window,
+            tabs: Vec::new(),
+            current: 0,
+            ipc,
+        }
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    pub fn tab(&self) -> &Tab {
+        &self.tabs[self.current]
+    }
+
+    pub fn tab_mut(&mut self) -> &mut Tab {
+        &mut self.tabs[self.current]
+    }
+
+    pub fn webview(&self) -> &WebView {
+        &self.tab().webview
+    }
+
+    pub fn tab_index(&self, id: TabId) -> Option<usize> {
+        self.tabs.iter().position(|tab| tab.id == id)
+    }
+
+    pub fn resize(&self) {
+        let bounds = bounds(&self.window);
+        for tab in &self.tabs {
+            let _ = tab.webview.set_bounds(bounds);
+        }
+    }
+
+    /// Shows the title of the current tab in the window and refreshes the
+    /// tab bar, whose titles may have changed.
+    pub fn update_title(&self) {
+        self.window.set_title(self.tab().title());
+        self.show_tabs();
+    }
+
+    pub fn show_tabs(&self) {
+        let titles: Vec<&str> = self.tabs.iter().map(Tab::title).collect();
+        self.webview()
+            .call_locked(self.ipc.token(), "window.showTabs", (titles, self.current));
+    }
+
+    /// Makes the tab at `index` the visible and focused one.
+    pub fn select(&mut self, index: usize) {
+        if let Some(tab) = self.tabs.get(self.current) {
+            let _ = tab.webview.set_visible(false);
+        }
+        self.current = index;
+        let _ = self.webview().set_visible(true);
+        let _ = self.webview().focus();
+        self.update_title();
+    }
+
+    /// Moves the current tab to `target`, keeping it selected.
+    pub fn move_tab(&mut self, target: usize) {
+        let tab = self.tabs.remove(self.current);
+        self.tabs.insert(target, tab);
+        self.current = target;
+        self.show_tabs();
+    }
+}
+
+/// The area of a window covered by a tab.
+pub fn bounds(window: &Window) -> Rect {
+    Rect {
+        position: LogicalPosition::new(0, 0).into(),
+        size: window.inner_size().into(),
+    }
+}
+
+/* window.rs ends here */